@@ -0,0 +1,208 @@
+// axis.rs
+// Turns absolute axes of gamepads and joysticks (sticks, hats, analog triggers) into press/release triggers
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+
+use crate::evdev::{self, AbsInfo, EV_ABS};
+use crate::key_codes;
+
+// An axis direction is pressed past 70% of its range and released again below 50%.
+// The gap between the two thresholds keeps a stick resting near the threshold from retriggering.
+const PRESS_THRESHOLD: f32 = 0.7;
+const RELEASE_THRESHOLD: f32 = 0.5;
+
+// Axes that are analog triggers or throttles on gamepads and joysticks: ABS_Z, ABS_RZ, ABS_THROTTLE, ABS_GAS, ABS_BRAKE
+const ANALOG_TRIGGER_AXES: [u16; 5] = [0x02, 0x05, 0x06, 0x09, 0x0a];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Neutral,
+    Positive,
+    Negative,
+}
+
+struct Axis {
+    minimum: i32,
+    maximum: i32,
+    one_sided: bool,    // Analog triggers rest at minimum and only move in one direction
+    direction: Direction,
+}
+
+impl Axis {
+    fn new(code: u16, info: &AbsInfo) -> Self {
+        Axis {
+            minimum: info.minimum,
+            maximum: info.maximum,
+            one_sided: is_one_sided(code, info),
+            direction: Direction::Neutral,
+        }
+    }
+    
+    // Position from -1.0 to 1.0 (0.0 to 1.0 for one-sided axes)
+    fn normalize(&self, value: i32) -> f32 {
+        let range = (self.maximum - self.minimum) as f32;
+        if range <= 0.0 {
+            return 0.0;
+        }
+
+        let position = (value - self.minimum) as f32 / range;
+        if self.one_sided {
+            position
+        } else {
+            position * 2.0 - 1.0
+        }
+    }
+}
+
+// Analog triggers (e.g. LT/RT) rest at their minimum and only move in one direction. Decided by the range,
+// not the value when the device is opened, which is wrong for a trigger held at that moment: a trigger axis
+// without negative values is one-sided, sticks and hats are centered. Sticks of some pads (e.g. 0..255)
+// have no negative values either, so only the axes gamepads use for triggers count.
+fn is_one_sided(code: u16, info: &AbsInfo) -> bool {
+    info.minimum >= 0 && info.maximum > info.minimum && ANALOG_TRIGGER_AXES.contains(&code)
+}
+
+// Trigger change of an axis: name and whether it was pressed (true) or released (false)
+pub type AxisTrigger = (&'static str, bool);
+
+pub struct AxisTriggers {
+    axes: HashMap<u16, Axis>,
+}
+
+impl AxisTriggers {
+    // No axes, for devices that are no gamepads
+    pub fn none() -> Self {
+        AxisTriggers { axes: HashMap::new() }
+    }
+
+    // Reads the range of every trigger axis of a device
+    pub fn read(fd: RawFd) -> Self {
+        let Ok(supported) = evdev::read_bits(fd, EV_ABS, evdev::ABS_MAX) else {
+            return Self::none();
+        };
+
+        let axes = (0..=evdev::ABS_MAX)
+            .filter(|&code| supported.has(code) && key_codes::is_abs_trigger(code))
+            .filter_map(|code| evdev::read_abs_info(fd, code).ok().map(|info| (code, info)));
+        Self::new(axes)
+    }
+
+    fn new(axes: impl IntoIterator<Item = (u16, AbsInfo)>) -> Self {
+        AxisTriggers { axes: axes.into_iter().map(|(code, info)| (code, Axis::new(code, &info))).collect() }
+    }
+
+    // Feeds a new axis value, returns the triggers that were released and pressed by it.
+    // A hat switch can jump from one direction to the other, that releases one and presses the other.
+    pub fn update(&mut self, code: u16, value: i32) -> Vec<AxisTrigger> {
+        let mut changes = Vec::new();
+        let Some(axis) = self.axes.get_mut(&code) else {
+            return changes;
+        };
+
+        let position = axis.normalize(value);
+
+        // Release first
+        let released = match axis.direction {
+            Direction::Positive => position < RELEASE_THRESHOLD,
+            Direction::Negative => position > -RELEASE_THRESHOLD,
+            Direction::Neutral => false,
+        };
+        if released {
+            if let Some(name) = key_codes::abs_to_string(code, axis.direction == Direction::Positive) {
+                changes.push((name, false));
+            }
+            axis.direction = Direction::Neutral;
+        }
+
+        if axis.direction == Direction::Neutral {
+            if position >= PRESS_THRESHOLD {
+                axis.direction = Direction::Positive;
+            } else if position <= -PRESS_THRESHOLD {
+                axis.direction = Direction::Negative;
+            }
+
+            if axis.direction != Direction::Neutral
+                && let Some(name) = key_codes::abs_to_string(code, axis.direction == Direction::Positive) {
+                changes.push((name, true));
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABS_X: u16 = 0x00;
+    const ABS_Z: u16 = 0x02;
+    const ABS_HAT0X: u16 = 0x10;
+
+    fn range(minimum: i32, maximum: i32, value: i32) -> AbsInfo {
+        AbsInfo { value, minimum, maximum, ..Default::default() }
+    }
+
+    #[test]
+    fn stick_presses_past_70_percent() {
+        let mut axes = AxisTriggers::new([(ABS_X, range(-32768, 32767, 0))]);
+
+        assert!(axes.update(ABS_X, 20000).is_empty()); // ~61%
+        assert_eq!(axes.update(ABS_X, 24000), vec![("LEFTSTICK-RIGHT", true)]);
+        assert!(axes.update(ABS_X, 30000).is_empty());
+        assert_eq!(axes.update(ABS_X, 0), vec![("LEFTSTICK-RIGHT", false)]);
+        assert_eq!(axes.update(ABS_X, -32768), vec![("LEFTSTICK-LEFT", true)]);
+    }
+
+    #[test]
+    fn stick_between_the_thresholds_does_not_retrigger() {
+        let mut axes = AxisTriggers::new([(ABS_X, range(-32768, 32767, 0))]);
+
+        assert_eq!(axes.update(ABS_X, 24000), vec![("LEFTSTICK-RIGHT", true)]);
+        // Wobbling between 50% and 70% neither releases nor presses again
+        for value in [19000, 22000, 17000, 22900] {
+            assert!(axes.update(ABS_X, value).is_empty());
+        }
+        assert_eq!(axes.update(ABS_X, 15000), vec![("LEFTSTICK-RIGHT", false)]); // ~46%
+        assert!(axes.update(ABS_X, 20000).is_empty());
+    }
+
+    #[test]
+    fn hat_jumping_to_the_other_side_releases_and_presses() {
+        let mut axes = AxisTriggers::new([(ABS_HAT0X, range(-1, 1, 0))]);
+
+        assert_eq!(axes.update(ABS_HAT0X, -1), vec![("HAT-LEFT", true)]);
+        assert_eq!(axes.update(ABS_HAT0X, 1), vec![("HAT-LEFT", false), ("HAT-RIGHT", true)]);
+        assert_eq!(axes.update(ABS_HAT0X, 0), vec![("HAT-RIGHT", false)]);
+    }
+
+    #[test]
+    fn trigger_is_one_sided_even_when_held_at_start() {
+        // Value at maximum when the device was opened, still a trigger resting at 0
+        let mut axes = AxisTriggers::new([(ABS_Z, range(0, 255, 255))]);
+
+        assert!(axes.update(ABS_Z, 150).is_empty()); // ~59%
+        assert_eq!(axes.update(ABS_Z, 200), vec![("ABS_Z+", true)]);
+        assert!(axes.update(ABS_Z, 140).is_empty());
+        assert_eq!(axes.update(ABS_Z, 100), vec![("ABS_Z+", false)]);
+        assert!(axes.update(ABS_Z, 0).is_empty());
+    }
+
+    #[test]
+    fn centered_axes_are_two_sided() {
+        assert!(!is_one_sided(ABS_Z, &range(-128, 127, -128)));
+        assert!(!is_one_sided(ABS_X, &range(0, 255, 0))); // Stick of a pad without negative values
+        assert!(!is_one_sided(ABS_HAT0X, &range(-1, 1, 0)));
+        assert!(is_one_sided(ABS_Z, &range(0, 1023, 512)));
+
+        // A 0..255 stick is centered at 128
+        let mut axes = AxisTriggers::new([(ABS_X, range(0, 255, 128))]);
+        assert_eq!(axes.update(ABS_X, 10), vec![("LEFTSTICK-LEFT", true)]);
+    }
+
+    #[test]
+    fn unknown_axes_are_ignored() {
+        let mut axes = AxisTriggers::new([(ABS_X, range(-32768, 32767, 0))]);
+        assert!(axes.update(0x35, 1000).is_empty()); // ABS_MT_POSITION_X
+    }
+}
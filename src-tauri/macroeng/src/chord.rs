@@ -0,0 +1,220 @@
+// chord.rs
+// Chords: two or more keys of one device held down together ("A+S") trigger their own item.
+// A key that is part of a bound chord is held back until it is clear whether it is used alone:
+// another key going down makes it a chord, releasing it or holding it past the chord timeout makes it a single key.
+// The chord is reported at its first release, so a chord of three keys is not cut short after two.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::key_codes;
+use crate::KeyboardListener::{KeyInfo, KeyState};
+
+// Whether a key is part of a chord bound on the device (key name, device name, device id)
+pub type ChordFilter = Arc<dyn Fn(&str, &str, &str) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct DeviceKeys {
+    device: String,         // Device name for the events of poll()
+    pending: Vec<String>,   // Held back keys in the order they went down
+    since: Option<Instant>, // When the first pending key went down
+    swallowed: Vec<String>, // Keys of a reported chord, their repeats and release are dropped
+}
+
+pub struct ChordRecognizer {
+    timeout: Duration,
+    devices: HashMap<String, DeviceKeys>,  // Device id -> keys, identical devices are kept apart
+}
+
+impl ChordRecognizer {
+    pub fn new(timeout: Duration) -> Self {
+        ChordRecognizer { timeout, devices: HashMap::new() }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // Feeds a key event, returns the events to pass on: the event itself, nothing while keys are held back,
+    // or the held back keys / the chord ("A+S", down and up) once decided.
+    // `is_member` tells whether a key is part of a bound chord, `is_bound` whether a chord name is bound.
+    pub fn feed(&mut self, info: &KeyInfo, now: Instant, is_member: impl Fn(&str) -> bool, is_bound: impl Fn(&str) -> bool) -> Vec<KeyInfo> {
+        let keys = self.devices.entry(info.device_id.clone()).or_default();
+        keys.device.clone_from(&info.device);
+        let is_key = key_codes::string_to_key(&info.name).is_some();
+
+        match info.state {
+            KeyState::Down => {
+                if !keys.pending.is_empty() && is_key {
+                    // Joins the chord that is being formed
+                    keys.pending.push(info.name.clone());
+                    Vec::new()
+                } else if keys.pending.is_empty() && is_key && is_member(&info.name) {
+                    keys.pending.push(info.name.clone());
+                    keys.since = Some(now);
+                    Vec::new()
+                } else {
+                    vec![copy_info(info, &info.name, info.state)]
+                }
+            },
+            KeyState::Press => {
+                if keys.pending.contains(&info.name) || keys.swallowed.contains(&info.name) {
+                    Vec::new()
+                } else {
+                    vec![copy_info(info, &info.name, info.state)]
+                }
+            },
+            KeyState::Up => {
+                if let Some(index) = keys.swallowed.iter().position(|key| *key == info.name) {
+                    keys.swallowed.remove(index);
+                    return Vec::new();
+                }
+
+                if !keys.pending.contains(&info.name) {
+                    return vec![copy_info(info, &info.name, info.state)];
+                }
+
+                let pending = std::mem::take(&mut keys.pending);
+                keys.since = None;
+
+                if pending.len() >= 2 {
+                    let chord = key_codes::chord_name(&pending);
+                    if is_bound(&chord) {
+                        // The other keys of the chord are still down, drop their release
+                        keys.swallowed.extend(pending.into_iter().filter(|key| *key != info.name));
+                        return vec![
+                            copy_info(info, &chord, KeyState::Down),
+                            copy_info(info, &chord, KeyState::Up),
+                        ];
+                    }
+                }
+
+                // No chord: the held back keys were pressed one after the other
+                let mut events: Vec<KeyInfo> = pending.iter()
+                    .map(|key| copy_info(info, key, KeyState::Down))
+                    .collect();
+                events.push(copy_info(info, &info.name, KeyState::Up));
+                events
+            },
+        }
+    }
+
+    // Keys held alone past the timeout are single keys (e.g. for a hold gesture)
+    pub fn poll(&mut self, now: Instant) -> Vec<KeyInfo> {
+        let mut events = Vec::new();
+
+        for (device_id, keys) in self.devices.iter_mut() {
+            let timed_out = keys.since.is_some_and(|since| now.duration_since(since) >= self.timeout);
+            if keys.pending.len() == 1 && timed_out {
+                let key = keys.pending.remove(0);
+                keys.since = None;
+                events.push(KeyInfo { name: key, state: KeyState::Down, device: keys.device.clone(), device_id: device_id.clone(), delta: 0 });
+            }
+        }
+
+        events
+    }
+
+    // When poll() has to be called next, None if no single key is held back
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.devices.values()
+            .filter(|keys| keys.pending.len() == 1)
+            .filter_map(|keys| keys.since.map(|since| since + self.timeout))
+            .min()
+    }
+}
+
+fn copy_info(info: &KeyInfo, name: &str, state: KeyState) -> KeyInfo {
+    KeyInfo { name: name.to_string(), state, device: info.device.clone(), device_id: info.device_id.clone(), delta: info.delta }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, state: KeyState, device_id: &str) -> KeyInfo {
+        KeyInfo { name: name.to_string(), state, device: "Pad".to_string(), device_id: device_id.to_string(), delta: 0 }
+    }
+
+    fn events(infos: &[KeyInfo]) -> Vec<(&str, KeyState)> {
+        infos.iter().map(|info| (info.name.as_str(), info.state)).collect()
+    }
+
+    // A and S form the bound chord "A+S"
+    fn is_member(key: &str) -> bool {
+        key == "A" || key == "S"
+    }
+
+    fn is_bound(chord: &str) -> bool {
+        chord == "A+S"
+    }
+
+    #[test]
+    fn chord_suppresses_its_single_keys() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        assert!(recognizer.feed(&key("S", KeyState::Down, "pad-1"), now, is_member, is_bound).is_empty());
+        assert!(recognizer.feed(&key("A", KeyState::Down, "pad-1"), now, is_member, is_bound).is_empty());
+        assert!(recognizer.feed(&key("A", KeyState::Press, "pad-1"), now, is_member, is_bound).is_empty());
+        let chord = recognizer.feed(&key("S", KeyState::Up, "pad-1"), now, is_member, is_bound);
+        assert_eq!(events(&chord), [("A+S", KeyState::Down), ("A+S", KeyState::Up)]);
+        // The release of the other key belongs to the chord
+        assert!(recognizer.feed(&key("A", KeyState::Up, "pad-1"), now, is_member, is_bound).is_empty());
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn member_released_alone_is_a_single_key() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        assert!(recognizer.feed(&key("A", KeyState::Down, "pad-1"), now, is_member, is_bound).is_empty());
+        let released = recognizer.feed(&key("A", KeyState::Up, "pad-1"), now, is_member, is_bound);
+        assert_eq!(events(&released), [("A", KeyState::Down), ("A", KeyState::Up)]);
+    }
+
+    #[test]
+    fn member_held_past_the_timeout_is_a_single_key() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        recognizer.feed(&key("A", KeyState::Down, "pad-1"), now, is_member, is_bound);
+        assert_eq!(recognizer.next_deadline(), Some(now + Duration::from_millis(150)));
+        assert!(recognizer.poll(now + Duration::from_millis(149)).is_empty());
+        assert_eq!(events(&recognizer.poll(now + Duration::from_millis(150))), [("A", KeyState::Down)]);
+        assert_eq!(events(&recognizer.feed(&key("A", KeyState::Up, "pad-1"), now, is_member, is_bound)), [("A", KeyState::Up)]);
+    }
+
+    #[test]
+    fn unbound_chord_passes_the_keys_on() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        recognizer.feed(&key("A", KeyState::Down, "pad-1"), now, is_member, is_bound);
+        recognizer.feed(&key("D", KeyState::Down, "pad-1"), now, is_member, is_bound);
+        let released = recognizer.feed(&key("D", KeyState::Up, "pad-1"), now, is_member, is_bound);
+        assert_eq!(events(&released), [("A", KeyState::Down), ("D", KeyState::Down), ("D", KeyState::Up)]);
+    }
+
+    #[test]
+    fn other_keys_are_not_held_back() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        assert_eq!(events(&recognizer.feed(&key("B", KeyState::Down, "pad-1"), now, is_member, is_bound)), [("B", KeyState::Down)]);
+        assert_eq!(events(&recognizer.feed(&key("B", KeyState::Up, "pad-1"), now, is_member, is_bound)), [("B", KeyState::Up)]);
+    }
+
+    #[test]
+    fn identical_devices_do_not_form_a_chord() {
+        let mut recognizer = ChordRecognizer::new(Duration::from_millis(150));
+        let now = Instant::now();
+
+        recognizer.feed(&key("A", KeyState::Down, "pad-1"), now, is_member, is_bound);
+        recognizer.feed(&key("S", KeyState::Down, "pad-2"), now, is_member, is_bound);
+        let released = recognizer.feed(&key("S", KeyState::Up, "pad-2"), now, is_member, is_bound);
+        assert_eq!(events(&released), [("S", KeyState::Down), ("S", KeyState::Up)]);
+        assert_eq!(released[0].device_id, "pad-2");
+    }
+}
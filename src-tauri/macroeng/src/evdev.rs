@@ -0,0 +1,156 @@
+// evdev.rs
+// ioctl helpers for /dev/input/event* devices (see linux/input.h)
+use std::io;
+use std::os::unix::io::RawFd;
+
+use crate::input_source::InputEvent;
+
+// ioctl direction bits
+pub(crate) const IOC_NONE: u32 = 0;
+pub(crate) const IOC_WRITE: u32 = 1;
+pub(crate) const IOC_READ: u32 = 2;
+
+// Builds an ioctl request number like the _IOC macro in asm-generic/ioctl.h
+pub(crate) const fn ioc(dir: u32, type_: u8, nr: u8, size: usize) -> u32 {
+    (dir << 30) | ((size as u32) << 16) | ((type_ as u32) << 8) | nr as u32
+}
+
+// Event types
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const EV_MSC: u16 = 0x04;  // Scan codes (MSC_SCAN) next to the key events
+pub const EV_LED: u16 = 0x11;
+
+// Highest code per event type
+pub const EV_MAX: u16 = 0x1f;
+pub const KEY_MAX: u16 = 0x2ff;
+pub const REL_MAX: u16 = 0x0f;
+pub const ABS_MAX: u16 = 0x3f;
+pub const MSC_SCAN: u16 = 0x04;
+pub const MSC_MAX: u16 = 0x07;
+pub const LED_MAX: u16 = 0x0f;
+
+pub const EVIOCGID: u32 = ioc(IOC_READ, b'E', 0x02, std::mem::size_of::<InputId>());
+pub const EVIOCGRAB: u32 = ioc(IOC_WRITE, b'E', 0x90, std::mem::size_of::<libc::c_int>());
+
+// EVIOCGBIT(0, len) returns the supported event types, EVIOCGBIT(type, len) the codes of one type
+const fn eviocgbit(ev_type: u16, len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x20 + ev_type as u8, len)
+}
+
+// EVIOCGABS(abs): range and current value of an absolute axis
+const fn eviocgabs(abs: u16) -> u32 {
+    ioc(IOC_READ, b'E', 0x40 + abs as u8, std::mem::size_of::<AbsInfo>())
+}
+
+const fn eviocgphys(len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x07, len)
+}
+
+const fn eviocguniq(len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x08, len)
+}
+
+// struct input_id
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+// struct input_absinfo
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct AbsInfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+// Reads bus type, vendor, product and version of a device
+pub fn read_id(fd: RawFd) -> io::Result<InputId> {
+    let mut id = InputId::default();
+    if unsafe { libc::ioctl(fd, EVIOCGID as _, &mut id as *mut InputId) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(id)
+}
+
+// Reads range and current value of an absolute axis
+pub fn read_abs_info(fd: RawFd, abs: u16) -> io::Result<AbsInfo> {
+    let mut info = AbsInfo::default();
+    if unsafe { libc::ioctl(fd, eviocgabs(abs) as _, &mut info as *mut AbsInfo) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(info)
+}
+
+// Reads the physical path of a device, e.g. "usb-0000:00:14.0-2/input0"
+pub fn read_phys(fd: RawFd) -> io::Result<String> {
+    read_string(fd, eviocgphys)
+}
+
+// Reads the unique identifier (serial number) of a device, empty for most keyboards
+pub fn read_uniq(fd: RawFd) -> io::Result<String> {
+    read_string(fd, eviocguniq)
+}
+
+// Switches an LED of a device, the fd has to be opened for writing
+pub fn write_led(fd: RawFd, led: u16, on: bool) -> io::Result<()> {
+    for event in [InputEvent::new(EV_LED, led, on as i32), InputEvent::new(EV_SYN, 0, 0)] {
+        let written = unsafe {
+            libc::write(fd, &event as *const InputEvent as *const libc::c_void, std::mem::size_of::<InputEvent>())
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// Capability bitmap of one event type (0 = event types), bit n set if code n is supported
+pub struct Bits(Vec<u8>);
+
+impl Bits {
+    pub fn has(&self, code: u16) -> bool {
+        self.0.get(code as usize / 8).is_some_and(|byte| byte & (1 << (code % 8)) != 0)
+    }
+
+    // Whether any code in the range is supported
+    pub fn any(&self, codes: std::ops::RangeInclusive<u16>) -> bool {
+        codes.into_iter().any(|code| self.has(code))
+    }
+}
+
+pub fn read_bits(fd: RawFd, ev_type: u16, max_code: u16) -> io::Result<Bits> {
+    let mut buffer = vec![0u8; max_code as usize / 8 + 1];
+    if unsafe { libc::ioctl(fd, eviocgbit(ev_type, buffer.len()) as _, buffer.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Bits(buffer))
+}
+
+fn read_string(fd: RawFd, request: fn(usize) -> u32) -> io::Result<String> {
+    let mut buffer = [0u8; 256];
+    let len = unsafe { libc::ioctl(fd, request(buffer.len()) as _, buffer.as_mut_ptr()) };
+    if len < 0 {
+        let error = io::Error::last_os_error();
+        // ENOENT: the driver has no value for this property
+        if error.raw_os_error() == Some(libc::ENOENT) {
+            return Ok(String::new());
+        }
+        return Err(error);
+    }
+
+    let bytes = &buffer[..len as usize];
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
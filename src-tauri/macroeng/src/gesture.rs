@@ -0,0 +1,544 @@
+// gesture.rs
+// Turns key down/up events into gestures: single tap, double tap and hold.
+// GestureRecognizer is only the state machine, the time is passed in so it can be fed with
+// recorded or synthetic events. Gestures runs it on the keys of the monitors and fires on_gesture.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+
+use crate::chord::{ChordFilter, ChordRecognizer};
+use crate::event_handler::EventHandler;
+use crate::sequence::{SequenceEvent, SequenceFilter, SequenceRecognizer, SequenceStep};
+use crate::KeyboardListener::{KeyInfo, KeyState};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gesture {
+    #[default]
+    Tap,        // Pressed and released once
+    #[serde(rename = "doubletap")]
+    DoubleTap,  // Pressed twice within double_tap_ms
+    Hold,       // Held down for hold_ms
+}
+
+impl Gesture {
+    pub const ALL: [Gesture; 3] = [Gesture::Tap, Gesture::DoubleTap, Gesture::Hold];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gesture::Tap => "tap",
+            Gesture::DoubleTap => "doubletap",
+            Gesture::Hold => "hold",
+        }
+    }
+}
+
+impl Display for Gesture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Thresholds of the gesture recognition, saved in settings.json
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GestureTimings {
+    pub hold_ms: u64,        // A key held down this long is a hold
+    pub double_tap_ms: u64,  // A second press within this time after the release is a double tap
+    #[serde(default = "default_chord_ms")]
+    pub chord_ms: u64,       // Keys of a chord have to go down within this time
+    #[serde(default = "default_sequence_ms")]
+    pub sequence_ms: u64,    // Each key of a sequence has to follow the previous one within this time
+}
+
+fn default_chord_ms() -> u64 {
+    150
+}
+
+fn default_sequence_ms() -> u64 {
+    1000
+}
+
+impl Default for GestureTimings {
+    fn default() -> Self {
+        GestureTimings { hold_ms: 500, double_tap_ms: 300, chord_ms: default_chord_ms(), sequence_ms: default_sequence_ms() }
+    }
+}
+
+// A recognized gesture
+#[derive(Clone, Debug, PartialEq)]
+pub struct GestureInfo {
+    pub name: String,    // Key name like in KeyInfo
+    pub device: String,
+    pub device_id: String,
+    pub gesture: Gesture,
+    pub delta: i32,      // Steps of a wheel or knob trigger, 0 for keys
+}
+
+// Whether an item is bound to this gesture of a key (key name, device name, device id, gesture).
+// Keys without a double tap or hold binding report their tap right on key down, without waiting.
+pub type GestureFilter = Arc<dyn Fn(&str, &str, &str, Gesture) -> bool + Send + Sync>;
+
+enum KeyPhase {
+    Pressed { since: Instant },  // Down, not yet decided
+    Released { at: Instant },    // Released once, waiting for a second press
+    Done,                        // Gesture reported, the release is ignored
+}
+
+struct KeyStatus {
+    device: String,  // Device name
+    phase: KeyPhase,
+    delta: i32,
+    hold_bound: bool,
+    double_tap_bound: bool,
+}
+
+pub struct GestureRecognizer {
+    timings: GestureTimings,
+    keys: HashMap<(String, String), KeyStatus>,  // (device id, key) -> status
+}
+
+impl GestureRecognizer {
+    pub fn new(timings: GestureTimings) -> Self {
+        GestureRecognizer { timings, keys: HashMap::new() }
+    }
+
+    pub fn set_timings(&mut self, timings: GestureTimings) {
+        self.timings = timings;
+    }
+
+    fn hold_time(&self) -> Duration {
+        Duration::from_millis(self.timings.hold_ms)
+    }
+
+    fn double_tap_time(&self) -> Duration {
+        Duration::from_millis(self.timings.double_tap_ms)
+    }
+
+    // Key pressed on the device (name and DeviceInfo::device_id). `bound` tells which gestures are worth waiting for.
+    pub fn key_down(&mut self, key: &str, device: &str, device_id: &str, delta: i32, now: Instant, bound: impl Fn(Gesture) -> bool) -> Vec<GestureInfo> {
+        let id = (device_id.to_string(), key.to_string());
+        let double_tap_time = self.double_tap_time();
+        let mut gestures = Vec::new();
+
+        // Second press of a double tap
+        if let Some(status) = self.keys.get_mut(&id) && let KeyPhase::Released { at } = status.phase {
+            if now.duration_since(at) < double_tap_time {
+                status.phase = KeyPhase::Done;
+                gestures.push(gesture_info(&id, device, Gesture::DoubleTap, delta));
+                return gestures;
+            }
+            // Too late, the first press was a tap on its own
+            gestures.push(gesture_info(&id, device, Gesture::Tap, status.delta));
+        }
+
+        let hold_bound = bound(Gesture::Hold);
+        let double_tap_bound = bound(Gesture::DoubleTap);
+        let device = device.to_string();
+        if !hold_bound && !double_tap_bound {
+            // Nothing to wait for
+            gestures.push(gesture_info(&id, &device, Gesture::Tap, delta));
+            self.keys.insert(id, KeyStatus { device, phase: KeyPhase::Done, delta, hold_bound, double_tap_bound });
+            return gestures;
+        }
+
+        self.keys.insert(id, KeyStatus { device, phase: KeyPhase::Pressed { since: now }, delta, hold_bound, double_tap_bound });
+        gestures
+    }
+
+    // Key released
+    pub fn key_up(&mut self, key: &str, device_id: &str, now: Instant) -> Vec<GestureInfo> {
+        let id = (device_id.to_string(), key.to_string());
+        let hold_time = self.hold_time();
+        let mut gestures = Vec::new();
+
+        let Some(status) = self.keys.get_mut(&id) else {
+            return gestures;
+        };
+
+        match status.phase {
+            KeyPhase::Pressed { since } => {
+                if status.hold_bound && now.duration_since(since) >= hold_time {
+                    // poll() was not called in time
+                    gestures.push(gesture_info(&id, &status.device, Gesture::Hold, status.delta));
+                    self.keys.remove(&id);
+                } else if status.double_tap_bound {
+                    status.phase = KeyPhase::Released { at: now };
+                } else {
+                    gestures.push(gesture_info(&id, &status.device, Gesture::Tap, status.delta));
+                    self.keys.remove(&id);
+                }
+            },
+            KeyPhase::Done => {
+                self.keys.remove(&id);
+            },
+            KeyPhase::Released { .. } => (),
+        }
+
+        gestures
+    }
+
+    // Reports the gestures that are decided by time alone: holds and taps without a second press
+    pub fn poll(&mut self, now: Instant) -> Vec<GestureInfo> {
+        let hold_time = self.hold_time();
+        let double_tap_time = self.double_tap_time();
+        let mut gestures = Vec::new();
+
+        self.keys.retain(|id, status| {
+            match status.phase {
+                KeyPhase::Pressed { since } if status.hold_bound && now.duration_since(since) >= hold_time => {
+                    gestures.push(gesture_info(id, &status.device, Gesture::Hold, status.delta));
+                    status.phase = KeyPhase::Done;
+                    true
+                },
+                KeyPhase::Released { at } if now.duration_since(at) >= double_tap_time => {
+                    gestures.push(gesture_info(id, &status.device, Gesture::Tap, status.delta));
+                    false
+                },
+                _ => true,
+            }
+        });
+
+        gestures
+    }
+
+    // When poll() has to be called next, None if no key is waiting for a decision
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.keys.values()
+            .filter_map(|status| match status.phase {
+                KeyPhase::Pressed { since } if status.hold_bound => Some(since + self.hold_time()),
+                KeyPhase::Released { at } => Some(at + self.double_tap_time()),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+fn gesture_info(id: &(String, String), device: &str, gesture: Gesture, delta: i32) -> GestureInfo {
+    GestureInfo { name: id.1.clone(), device: device.to_string(), device_id: id.0.clone(), gesture, delta }
+}
+
+// Gesture recognition for the keys of all monitors.
+// Holds, single taps, keys held back for chords and sequence timeouts are decided on a timer thread,
+// on_gesture and on_sequence are called from there or from the monitor thread that reported the key.
+pub struct Gestures {
+    pub on_gesture: Arc<EventHandler<GestureInfo>>,
+    pub on_sequence: Arc<EventHandler<SequenceStep>>,
+    recognizers: Mutex<Recognizers>,
+    changed: Condvar,  // Wakes the timer thread when a deadline was added
+    filter: RwLock<Option<GestureFilter>>,
+    chord_filter: RwLock<Option<ChordFilter>>,
+    sequence_filter: RwLock<Option<SequenceFilter>>,
+}
+
+// Chords are resolved first, the gestures see the chord ("A+S") or the single keys,
+// the recognized taps then make up sequences ("G>C")
+struct Recognizers {
+    chords: ChordRecognizer,
+    gestures: GestureRecognizer,
+    sequences: SequenceRecognizer,
+}
+
+impl Recognizers {
+    fn next_deadline(&self) -> Option<Instant> {
+        [self.chords.next_deadline(), self.gestures.next_deadline(), self.sequences.next_deadline()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+impl Gestures {
+    pub fn new() -> &'static Self {
+        static GESTURES: OnceLock<Gestures> = OnceLock::new();
+        let mut created = false;
+        let gestures = GESTURES.get_or_init(|| {
+            created = true;
+            let timings = GestureTimings::default();
+            Gestures {
+                on_gesture: Arc::new(EventHandler::new()),
+                on_sequence: Arc::new(EventHandler::new()),
+                recognizers: Mutex::new(Recognizers {
+                    chords: ChordRecognizer::new(Duration::from_millis(timings.chord_ms)),
+                    gestures: GestureRecognizer::new(timings),
+                    sequences: SequenceRecognizer::new(Duration::from_millis(timings.sequence_ms)),
+                }),
+                changed: Condvar::new(),
+                filter: RwLock::new(None),
+                chord_filter: RwLock::new(None),
+                sequence_filter: RwLock::new(None),
+            }
+        });
+
+        if created && let Err(e) = thread::Builder::new().name("gestures".into()).spawn(move || gestures.run_timer()) {
+            eprintln!("Error starting gesture timer: {}", e);
+        }
+        gestures
+    }
+
+    pub fn set_timings(&self, timings: GestureTimings) {
+        if let Ok(mut recognizers) = self.recognizers.lock() {
+            recognizers.chords.set_timeout(Duration::from_millis(timings.chord_ms));
+            recognizers.sequences.set_timeout(Duration::from_millis(timings.sequence_ms));
+            recognizers.gestures.set_timings(timings);
+        }
+        self.changed.notify_all();
+    }
+
+    // Which gestures are bound, None = every gesture of every key
+    pub fn set_filter(&self, filter: Option<GestureFilter>) {
+        if let Ok(mut current) = self.filter.write() {
+            *current = filter;
+        }
+    }
+
+    // Which keys are part of a bound chord, None = no chords, keys are never held back
+    pub fn set_chord_filter(&self, filter: Option<ChordFilter>) {
+        if let Ok(mut current) = self.chord_filter.write() {
+            *current = filter;
+        }
+    }
+
+    // Whether a bound sequence continues after a typed part, None = no sequences, taps are never held back
+    pub fn set_sequence_filter(&self, filter: Option<SequenceFilter>) {
+        if let Ok(mut current) = self.sequence_filter.write() {
+            *current = filter;
+        }
+    }
+
+    // Drops the sequences that are being typed
+    pub fn cancel_sequences(&self) {
+        let events = match self.recognizers.lock() {
+            Ok(mut recognizers) => recognizers.sequences.cancel(),
+            Err(_) => return,
+        };
+        self.changed.notify_all();
+        self.trigger(&events);
+    }
+
+    fn is_bound(&self, key: &str, device: &str, device_id: &str, gesture: Gesture) -> bool {
+        match self.filter.read().ok().as_deref() {
+            Some(Some(filter)) => filter(key, device, device_id, gesture),
+            _ => true,
+        }
+    }
+
+    fn is_chord_member(&self, key: &str, device: &str, device_id: &str) -> bool {
+        match self.chord_filter.read().ok().as_deref() {
+            Some(Some(filter)) => filter(key, device, device_id),
+            _ => false,
+        }
+    }
+
+    fn continues_sequence(&self, typed: &str, device: &str, device_id: &str) -> bool {
+        match self.sequence_filter.read().ok().as_deref() {
+            Some(Some(filter)) => filter(typed, device, device_id),
+            _ => false,
+        }
+    }
+
+    // Passes the key events that are left after chord recognition on to the gesture recognizer
+    fn recognize(&self, gestures: &mut GestureRecognizer, keys: &[KeyInfo], now: Instant) -> Vec<GestureInfo> {
+        let mut recognized = Vec::new();
+        for info in keys {
+            recognized.extend(match info.state {
+                KeyState::Down => gestures.key_down(&info.name, &info.device, &info.device_id, info.delta, now,
+                    |gesture| self.is_bound(&info.name, &info.device, &info.device_id, gesture)),
+                KeyState::Up => gestures.key_up(&info.name, &info.device_id, now),
+                KeyState::Press => Vec::new(),
+            });
+        }
+        recognized
+    }
+
+    // Passes the recognized gestures on to the sequence recognizer
+    fn resolve_sequences(&self, sequences: &mut SequenceRecognizer, gestures: &[GestureInfo], now: Instant) -> Vec<SequenceEvent> {
+        let mut events = Vec::new();
+        for info in gestures {
+            events.extend(sequences.feed(info, now,
+                |typed| self.continues_sequence(typed, &info.device, &info.device_id),
+                |sequence| self.is_bound(sequence, &info.device, &info.device_id, Gesture::Tap)));
+        }
+        events
+    }
+
+    fn trigger(&self, events: &[SequenceEvent]) {
+        for event in events {
+            match event {
+                SequenceEvent::Gesture(gesture) => self.on_gesture.trigger(gesture),
+                SequenceEvent::Step(step) => self.on_sequence.trigger(step),
+            }
+        }
+    }
+
+    // Feeds a key event of a monitor, KeyState::Press (auto repeat) is ignored
+    pub fn feed(&self, info: &KeyInfo) {
+        if info.state == KeyState::Press {
+            return;
+        }
+
+        let now = Instant::now();
+        let events = {
+            let Ok(mut recognizers) = self.recognizers.lock() else { return };
+            let keys = recognizers.chords.feed(info, now,
+                |key| self.is_chord_member(key, &info.device, &info.device_id),
+                |chord| Gesture::ALL.iter().any(|&gesture| self.is_bound(chord, &info.device, &info.device_id, gesture)));
+            let gestures = self.recognize(&mut recognizers.gestures, &keys, now);
+            self.resolve_sequences(&mut recognizers.sequences, &gestures, now)
+        };
+        self.changed.notify_all();
+
+        self.trigger(&events);
+    }
+
+    fn run_timer(&self) {
+        let Ok(mut recognizers) = self.recognizers.lock() else { return };
+        loop {
+            recognizers = match recognizers.next_deadline() {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match self.changed.wait_timeout(recognizers, timeout) {
+                        Ok((recognizers, _)) => recognizers,
+                        Err(_) => return,
+                    }
+                },
+                None => match self.changed.wait(recognizers) {
+                    Ok(recognizers) => recognizers,
+                    Err(_) => return,
+                },
+            };
+
+            let now = Instant::now();
+            let keys = recognizers.chords.poll(now);
+            let mut gestures = self.recognize(&mut recognizers.gestures, &keys, now);
+            gestures.extend(recognizers.gestures.poll(now));
+            let mut events = recognizers.sequences.poll(now, |sequence, device, device_id| self.is_bound(sequence, device, device_id, Gesture::Tap));
+            events.extend(self.resolve_sequences(&mut recognizers.sequences, &gestures, now));
+            if events.is_empty() {
+                continue;
+            }
+
+            // Run the listeners (macros) without blocking the monitors
+            drop(recognizers);
+            self.trigger(&events);
+            recognizers = match self.recognizers.lock() {
+                Ok(recognizers) => recognizers,
+                Err(_) => return,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn gestures(infos: &[GestureInfo]) -> Vec<(&str, Gesture)> {
+        infos.iter().map(|info| (info.name.as_str(), info.gesture)).collect()
+    }
+
+    #[test]
+    fn tap_without_other_bindings_fires_on_key_down() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        let down = recognizer.key_down("A", "Pad", "pad-1", 0, start, |_| false);
+        assert_eq!(gestures(&down), [("A", Gesture::Tap)]);
+        assert!(recognizer.key_up("A", "pad-1", start + ms(50)).is_empty());
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn tap_waits_for_the_double_tap_time() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        assert!(recognizer.key_down("A", "Pad", "pad-1", 0, start, |_| true).is_empty());
+        assert!(recognizer.key_up("A", "pad-1", start + ms(100)).is_empty());
+        assert_eq!(recognizer.next_deadline(), Some(start + ms(400)));
+        assert!(recognizer.poll(start + ms(399)).is_empty());
+        assert_eq!(gestures(&recognizer.poll(start + ms(400))), [("A", Gesture::Tap)]);
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn second_press_in_time_is_a_double_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        recognizer.key_down("A", "Pad", "pad-1", 0, start, |_| true);
+        recognizer.key_up("A", "pad-1", start + ms(100));
+        let down = recognizer.key_down("A", "Pad", "pad-1", 0, start + ms(250), |_| true);
+        assert_eq!(gestures(&down), [("A", Gesture::DoubleTap)]);
+        assert!(recognizer.key_up("A", "pad-1", start + ms(300)).is_empty());
+        assert!(recognizer.poll(start + ms(2000)).is_empty());
+    }
+
+    #[test]
+    fn second_press_too_late_is_two_taps() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        recognizer.key_down("A", "Pad", "pad-1", 0, start, |gesture| gesture == Gesture::DoubleTap);
+        recognizer.key_up("A", "pad-1", start + ms(100));
+        // The timer did not run, the late press decides the first tap
+        let down = recognizer.key_down("A", "Pad", "pad-1", 0, start + ms(450), |gesture| gesture == Gesture::DoubleTap);
+        assert_eq!(gestures(&down), [("A", Gesture::Tap)]);
+        recognizer.key_up("A", "pad-1", start + ms(500));
+        assert_eq!(gestures(&recognizer.poll(start + ms(800))), [("A", Gesture::Tap)]);
+    }
+
+    #[test]
+    fn hold_fires_when_crossing_the_threshold() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        assert!(recognizer.key_down("A", "Pad", "pad-1", 0, start, |gesture| gesture == Gesture::Hold).is_empty());
+        assert_eq!(recognizer.next_deadline(), Some(start + ms(500)));
+        assert!(recognizer.poll(start + ms(499)).is_empty());
+        assert_eq!(gestures(&recognizer.poll(start + ms(500))), [("A", Gesture::Hold)]);
+        assert!(recognizer.key_up("A", "pad-1", start + ms(900)).is_empty());
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn hold_is_reported_on_release_if_poll_was_late() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        recognizer.key_down("A", "Pad", "pad-1", 0, start, |gesture| gesture == Gesture::Hold);
+        assert_eq!(gestures(&recognizer.key_up("A", "pad-1", start + ms(600))), [("A", Gesture::Hold)]);
+    }
+
+    #[test]
+    fn short_press_with_only_hold_bound_is_a_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        recognizer.key_down("A", "Pad", "pad-1", 0, start, |gesture| gesture == Gesture::Hold);
+        assert_eq!(gestures(&recognizer.key_up("A", "pad-1", start + ms(200))), [("A", Gesture::Tap)]);
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn identical_devices_are_kept_apart() {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::default());
+        let start = Instant::now();
+
+        recognizer.key_down("A", "Pad", "pad-1", 0, start, |_| true);
+        recognizer.key_up("A", "pad-1", start + ms(100));
+        // Same key and name on the other pad is not the second press
+        assert!(recognizer.key_down("A", "Pad", "pad-2", 0, start + ms(150), |_| true).is_empty());
+
+        let taps = recognizer.poll(start + ms(400));
+        assert_eq!(taps.len(), 1);
+        assert_eq!(taps[0].device_id, "pad-1");
+        assert_eq!(taps[0].device, "Pad");
+    }
+}
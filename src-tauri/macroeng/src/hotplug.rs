@@ -0,0 +1,101 @@
+// hotplug.rs
+// Watches /dev/input with inotify and reports event devices being added or removed
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::poller;
+
+// Time udev gets to finish setting up a new node before it is reported
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+pub enum HotplugEvent {
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+// Blocks the calling thread and calls `callback` for every added or removed /dev/input/event* node.
+// Added nodes are reported SETTLE_TIME later, a node removed before that is not reported at all.
+// Only returns on an inotify error.
+pub fn watch_input_dir<F>(dir: &Path, callback: F) -> io::Result<()>
+where
+    F: Fn(HotplugEvent),
+{
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let dir_c = CString::new(dir.as_os_str().as_encoded_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM;
+    if unsafe { libc::inotify_add_watch(fd, dir_c.as_ptr(), mask) } < 0 {
+        let error = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(error);
+    }
+
+    // Room for several events with file names
+    let mut buffer = [0u8; 4096];
+    let header_size = std::mem::size_of::<libc::inotify_event>();
+
+    // Added nodes and when they are reported, every node has its own deadline so other events are not held up
+    let mut settling = HashMap::<PathBuf, Instant>::new();
+
+    loop {
+        let mut settled: Vec<(PathBuf, Instant)> = settling.iter()
+            .filter(|&(_, &deadline)| deadline <= Instant::now())
+            .map(|(path, &deadline)| (path.clone(), deadline))
+            .collect();
+        settled.sort_by_key(|&(_, deadline)| deadline);
+        for (path, _) in settled {
+            settling.remove(&path);
+            callback(HotplugEvent::Added(path));
+        }
+
+        // Sleep until the next inotify event or the next deadline
+        let timeout = settling.values().min().map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let mut fds = [poller::pollfd(fd)];
+        match poller::poll_fds(&mut fds, timeout) {
+            Ok(0) => continue,
+            Ok(_) => (),
+            Err(error) => {
+                unsafe { libc::close(fd) };
+                return Err(error);
+            },
+        }
+
+        let len = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if len < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+
+        let mut offset = 0usize;
+        while offset + header_size <= len as usize {
+            let event = unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const libc::inotify_event) };
+            let name_bytes = &buffer[offset + header_size..offset + header_size + event.len as usize];
+            offset += header_size + event.len as usize;
+
+            // The name is padded with NUL bytes
+            let name_end = name_bytes.iter().position(|&b| b == 0).unwrap_or(name_bytes.len());
+            let name = String::from_utf8_lossy(&name_bytes[..name_end]);
+            if !name.starts_with("event") {
+                continue;
+            }
+
+            let path = dir.join(name.as_ref());
+            if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                settling.insert(path, Instant::now() + SETTLE_TIME);
+            } else if event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 && settling.remove(&path).is_none() {
+                callback(HotplugEvent::Removed(path));
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+// input_source.rs
+// Where the monitor of a device gets its events from: a /dev/input device (EvdevSource)
+// or a recorded event stream (ReplaySource), so key handling can run without root and hardware.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::axis::AxisTriggers;
+use crate::evdev::EVIOCGRAB;
+use crate::poller::{self, WakeFd};
+use crate::KeyboardListener::{DeviceCapabilities, DeviceKind};
+
+// Structure for Linux input events (struct input_event)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub tv_sec: libc::time_t,
+    pub tv_usec: libc::suseconds_t,
+    pub type_: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    pub fn new(type_: u16, code: u16, value: i32) -> Self {
+        InputEvent { tv_sec: 0, tv_usec: 0, type_, code, value }
+    }
+}
+
+// Result of waiting for the next event
+pub enum SourceEvent {
+    Event(InputEvent),
+    Woken,   // The wake fd was signalled, e.g. to stop the monitor
+    Closed,  // Device removed or recording finished
+}
+
+pub trait InputSource: Send {
+    // Blocks until the next event, a wake-up through `wake` or the end of the source
+    fn next_event(&mut self, wake: &WakeFd) -> io::Result<SourceEvent>;
+
+    // Takes exclusive access to the device, returns the fd to release the grab with
+    fn grab(&mut self) -> Option<RawFd> {
+        None
+    }
+
+    // Threshold triggers for the absolute axes of the source
+    fn axis_triggers(&self) -> AxisTriggers {
+        AxisTriggers::none()
+    }
+
+    // fd of the real device, used to mirror it as a virtual device
+    fn fd(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+// Non-blocking event reader, wait for input with poller::poll_fds before reading
+pub(crate) struct EventReader {
+    file: File,
+    buffer: [u8; std::mem::size_of::<InputEvent>()],
+}
+
+impl EventReader {
+    pub(crate) fn new(file: File) -> io::Result<Self> {
+        // Set non-blocking mode
+        let fd = file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL, 0) };
+        unsafe {
+            if libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(EventReader {
+            file,
+            buffer: [0u8; std::mem::size_of::<InputEvent>()],
+        })
+    }
+
+    pub(crate) fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    // Reads one event, None if no more events are pending.
+    // evdev always returns whole events, a short read means the device is broken.
+    pub(crate) fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+        match self.file.read(&mut self.buffer) {
+            Ok(len) if len == self.buffer.len() => {
+                let event = unsafe { std::ptr::read_unaligned(self.buffer.as_ptr() as *const InputEvent) };
+                Ok(Some(event))
+            },
+            Ok(_) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Short read from input device")),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => self.read_event(),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+// Events of a /dev/input/event* device
+pub struct EvdevSource {
+    reader: EventReader,
+}
+
+impl EvdevSource {
+    // Opens the device for reading and, if allowed, for writing (LEDs)
+    pub fn open(device_path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(device_path)
+            .or_else(|_| File::open(device_path))?;
+        Ok(EvdevSource { reader: EventReader::new(file)? })
+    }
+
+    // Drops all pending events
+    pub fn drain(&mut self) {
+        while let Ok(Some(_)) = self.reader.read_event() {}
+    }
+}
+
+impl InputSource for EvdevSource {
+    fn next_event(&mut self, wake: &WakeFd) -> io::Result<SourceEvent> {
+        loop {
+            if let Some(event) = self.reader.read_event()? {
+                return Ok(SourceEvent::Event(event));
+            }
+
+            // Nothing pending, sleep until the device has input or the monitor is stopped
+            let mut fds = [poller::pollfd(self.reader.fd()), poller::pollfd(wake.fd())];
+            poller::poll_fds(&mut fds, None)?;
+
+            if poller::is_readable(&fds[1]) {
+                wake.clear();
+                return Ok(SourceEvent::Woken);
+            }
+
+            if poller::is_closed(&fds[0]) && !poller::is_readable(&fds[0]) {
+                return Ok(SourceEvent::Closed);
+            }
+        }
+    }
+
+    fn grab(&mut self) -> Option<RawFd> {
+        let fd = self.reader.fd();
+        if unsafe { libc::ioctl(fd, EVIOCGRAB as _, 1) } < 0 {
+            eprintln!("Warning: Could not completely block device input (EVIOCGRAB failed).");
+            eprintln!("         This only works with root privileges.");
+            None
+        } else {
+            Some(fd)
+        }
+    }
+
+    fn axis_triggers(&self) -> AxisTriggers {
+        // Sticks, hats and analog triggers of gamepads (touchpads and tablets report EV_ABS too)
+        let fd = self.reader.fd();
+        let is_gamepad = DeviceCapabilities::read(fd).is_ok_and(|c| c.kind() == DeviceKind::Gamepad);
+        if is_gamepad { AxisTriggers::read(fd) } else { AxisTriggers::none() }
+    }
+
+    fn fd(&self) -> Option<RawFd> {
+        Some(self.reader.fd())
+    }
+}
+
+// Recording format (written by Recorder, read by ReplaySource):
+// One event per line, "<tv_sec>.<tv_usec> <type> <code> <value>" in decimal with tv_usec padded to
+// six digits, e.g. "1718000000.125000 1 30 1" (KEY_A pressed). The time is the kernel timestamp of the event.
+// Edited files may use fewer digits, the time is read as a decimal number ("1.5" is 1.5 s).
+// Lines starting with '#' are comments, "# device: <name>" names the recorded device.
+pub const DEVICE_HEADER: &str = "device";
+
+// Events from a recording. Played back with the recorded timing divided by `speed`
+// (1.0 = real time, 4.0 = four times as fast), speed 0 plays back as fast as the monitor takes them.
+pub struct ReplaySource {
+    events: std::iter::Peekable<std::vec::IntoIter<InputEvent>>,
+    speed: f64,
+    device_name: Option<String>,
+    last_time: Option<Duration>,  // Timestamp of the previous event
+}
+
+impl ReplaySource {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut source = Self::from_events(parse_recording(&text)?);
+        source.device_name = parse_device_name(&text);
+        Ok(source)
+    }
+
+    pub fn from_events(events: Vec<InputEvent>) -> Self {
+        ReplaySource {
+            events: events.into_iter().peekable(),
+            speed: 0.0,
+            device_name: None,
+            last_time: None,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = speed.max(0.0);
+        self
+    }
+
+    // Device name from the header of the recording
+    pub fn device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
+    }
+}
+
+impl InputSource for ReplaySource {
+    fn next_event(&mut self, wake: &WakeFd) -> io::Result<SourceEvent> {
+        let Some(&event) = self.events.peek() else {
+            return Ok(SourceEvent::Closed);
+        };
+
+        // Wait for the recorded gap to the previous event, a stop request ends the wait
+        let time = event_time(&event);
+        let delay = match self.last_time {
+            Some(last) if self.speed > 0.0 => time.saturating_sub(last).div_f64(self.speed),
+            _ => Duration::ZERO,
+        };
+
+        let mut fds = [poller::pollfd(wake.fd())];
+        if poller::poll_fds(&mut fds, Some(delay))? > 0 {
+            wake.clear();
+            return Ok(SourceEvent::Woken);
+        }
+
+        self.events.next();
+        self.last_time = Some(time);
+        Ok(SourceEvent::Event(event))
+    }
+}
+
+fn event_time(event: &InputEvent) -> Duration {
+    Duration::new(event.tv_sec.max(0) as u64, 0) + Duration::from_micros(event.tv_usec.max(0) as u64)
+}
+
+// Formats one event as a line of a recording
+pub fn format_event(event: &InputEvent) -> String {
+    format!("{}.{:06} {} {} {}", event.tv_sec, event.tv_usec, event.type_, event.code, event.value)
+}
+
+fn parse_device_name(text: &str) -> Option<String> {
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .filter_map(|comment| comment.trim().strip_prefix(DEVICE_HEADER)?.trim_start().strip_prefix(':'))
+        .map(|name| name.trim().to_string())
+        .next()
+}
+
+// Decimal places of the time as microseconds: "5" is 500000, "000005" is 5
+fn parse_usec(fraction: &str) -> Option<libc::suseconds_t> {
+    if fraction.is_empty() || fraction.len() > 6 || !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let digits: libc::suseconds_t = fraction.parse().ok()?;
+    Some(digits * libc::suseconds_t::pow(10, 6 - fraction.len() as u32))
+}
+
+// Parses a recording in the format described at DEVICE_HEADER
+pub fn parse_recording(text: &str) -> io::Result<Vec<InputEvent>> {
+    let mut events = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid event in line {}: '{}'", index + 1, line));
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [time, type_, code, value] = fields[..] else {
+            return Err(invalid());
+        };
+
+        let (sec, fraction) = time.split_once('.').unwrap_or((time, "0"));
+        events.push(InputEvent {
+            tv_sec: sec.parse().map_err(|_| invalid())?,
+            tv_usec: parse_usec(fraction).ok_or_else(invalid)?,
+            type_: type_.parse().map_err(|_| invalid())?,
+            code: code.parse().map_err(|_| invalid())?,
+            value: value.parse().map_err(|_| invalid())?,
+        });
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::KeyboardListener::{DeviceInfo, Instance, KeyState};
+
+    // A pressed, repeated and released, a wheel step down, B pressed and released
+    const RECORDING: &str = "\
+# device: Replay Test Pad
+1718000000.000000 1 30 1
+1718000000.000000 0 0 0
+1718000000.250000 1 30 2
+1718000000.250000 0 0 0
+
+1718000000.300000 1 30 0
+1718000000.300000 0 0 0
+1718000000.400000 2 8 -1
+1718000000.400000 0 0 0
+1718000001.500000 1 48 1
+1718000001.500000 1 48 0
+";
+
+    #[test]
+    fn parses_the_recording_format() {
+        let events = parse_recording(RECORDING).unwrap();
+        assert_eq!(events.len(), 10);
+        assert_eq!(events[0], InputEvent { tv_sec: 1718000000, tv_usec: 0, type_: 1, code: 30, value: 1 });
+        assert_eq!(events[6], InputEvent { tv_sec: 1718000000, tv_usec: 400000, type_: 2, code: 8, value: -1 });
+        assert_eq!(format_event(&events[2]), "1718000000.250000 1 30 2");
+        assert_eq!(parse_device_name(RECORDING).as_deref(), Some("Replay Test Pad"));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let error = parse_recording("1718000000.000000 1 30 1\n1718000000.000000 1 30\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"));
+        assert!(parse_recording("1718000000.000000 1 A 1").is_err());
+        assert!(parse_recording("1718000000.1234567 1 30 1").is_err());
+        assert!(parse_recording("1718000000.-5 1 30 1").is_err());
+    }
+
+    #[test]
+    fn reads_the_time_as_a_decimal_number() {
+        let events = parse_recording("1.5 1 30 1\n2.000005 1 30 0\n3 0 0 0\n4.25 0 0 0\n").unwrap();
+        let times: Vec<_> = events.iter().map(|event| (event.tv_sec, event.tv_usec)).collect();
+        assert_eq!(times, [(1, 500000), (2, 5), (3, 0), (4, 250000)]);
+    }
+
+    #[test]
+    fn replays_a_recording_through_a_monitor() {
+        let path = std::env::temp_dir().join(format!("macroeng-replay-{}.txt", std::process::id()));
+        fs::write(&path, RECORDING).unwrap();
+        let source = ReplaySource::open(&path);
+        fs::remove_file(&path).unwrap();
+        let source = source.unwrap();
+        assert_eq!(source.device_name(), Some("Replay Test Pad"));
+
+        let device = DeviceInfo {
+            device_name: "Replay Test Pad".to_string(),
+            device_path: "replay:input_source_test".to_string(),
+            ..Default::default()
+        };
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let instance = Instance::new();
+        let received = Arc::clone(&keys);
+        instance.on_key.add_listener(move |info| {
+            if info.device == "Replay Test Pad" {
+                received.lock().unwrap().push((info.name.clone(), info.state, info.device_id.clone(), info.delta));
+            }
+        });
+
+        instance.start_source(&device, Box::new(source)).unwrap().wait().unwrap();
+        assert!(!instance.is_monitoring(&device.device_path));
+
+        // A device without IDs is identified by its name
+        let id = "Replay Test Pad".to_string();
+        assert_eq!(*keys.lock().unwrap(), [
+            ("A".to_string(), KeyState::Down, id.clone(), 0),
+            ("A".to_string(), KeyState::Press, id.clone(), 0),
+            ("A".to_string(), KeyState::Up, id.clone(), 0),
+            ("WHEEL-DOWN".to_string(), KeyState::Down, id.clone(), -1),
+            ("WHEEL-DOWN".to_string(), KeyState::Up, id.clone(), -1),
+            ("B".to_string(), KeyState::Down, id.clone(), 0),
+            ("B".to_string(), KeyState::Up, id, 0),
+        ]);
+    }
+}
@@ -0,0 +1,829 @@
+// key_codes.rs
+// Shared key catalogue for the listener (input) and the Lua functions (output).
+// Built from linux/input-event-codes.h: every KEY_* and BTN_* code has one canonical
+// name plus aliases. Header names are used without the "KEY_" prefix, BTN_* codes keep theirs
+// so they don't collide with letters (BTN_A vs. A).
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// (code, canonical name, aliases)
+// Every physical key has its own canonical name. Names the listener used before are kept where they were
+// unique and pointed at the right key. Renamed ones, the left/right modifiers and the digit row
+// (the old listener was off by one from 0 on: "10" was the 0 key), are listed in LEGACY_NAMES and migrated in items.json.
+static KEYS: &[(u16, &str, &[&str])] = &[
+    (1, "ESC", &["ESCAPE"]),
+    (2, "1", &[]),
+    (3, "2", &[]),
+    (4, "3", &[]),
+    (5, "4", &[]),
+    (6, "5", &[]),
+    (7, "6", &[]),
+    (8, "7", &[]),
+    (9, "8", &[]),
+    (10, "9", &[]),
+    (11, "0", &[]),
+    (12, "-", &["MINUS"]),
+    (13, "=", &["EQUAL"]),
+    (14, "BACKSPACE", &[]),
+    (15, "TAB", &[]),
+    (16, "Q", &[]),
+    (17, "W", &[]),
+    (18, "E", &[]),
+    (19, "R", &[]),
+    (20, "T", &[]),
+    (21, "Y", &[]),
+    (22, "U", &[]),
+    (23, "I", &[]),
+    (24, "O", &[]),
+    (25, "P", &[]),
+    (26, "[", &["LEFTBRACE"]),
+    (27, "]", &["RIGHTBRACE"]),
+    (28, "ENTER", &["RETURN"]),
+    (29, "LEFTCTRL", &["CTRL", "LCTRL", "CONTROL"]),
+    (30, "A", &[]),
+    (31, "S", &[]),
+    (32, "D", &[]),
+    (33, "F", &[]),
+    (34, "G", &[]),
+    (35, "H", &[]),
+    (36, "J", &[]),
+    (37, "K", &[]),
+    (38, "L", &[]),
+    (39, ";", &["SEMICOLON"]),
+    (40, "'", &["APOSTROPHE"]),
+    (41, "`", &["GRAVE"]),
+    (42, "LEFTSHIFT", &["LSHIFT", "SHIFT"]),
+    (43, "\\", &["BACKSLASH"]),
+    (44, "Z", &[]),
+    (45, "X", &[]),
+    (46, "C", &[]),
+    (47, "V", &[]),
+    (48, "B", &[]),
+    (49, "N", &[]),
+    (50, "M", &[]),
+    (51, ",", &["COMMA"]),
+    (52, ".", &["DOT", "PERIOD"]),
+    (53, "/", &["SLASH"]),
+    (54, "RIGHTSHIFT", &["RSHIFT"]),
+    (55, "KPASTERISK", &["NUM*"]),
+    (56, "LEFTALT", &["ALT", "LALT"]),
+    (57, "SPACE", &[]),
+    (58, "CAPSLOCK", &[]),
+    (59, "F1", &[]),
+    (60, "F2", &[]),
+    (61, "F3", &[]),
+    (62, "F4", &[]),
+    (63, "F5", &[]),
+    (64, "F6", &[]),
+    (65, "F7", &[]),
+    (66, "F8", &[]),
+    (67, "F9", &[]),
+    (68, "F10", &[]),
+    (69, "NUMLOCK", &[]),
+    (70, "SCROLLLOCK", &[]),
+    (71, "KP7", &["NUM7"]),
+    (72, "KP8", &["NUM8"]),
+    (73, "KP9", &["NUM9"]),
+    (74, "KPMINUS", &["NUM-"]),
+    (75, "KP4", &["NUM4"]),
+    (76, "KP5", &["NUM5"]),
+    (77, "KP6", &["NUM6"]),
+    (78, "KPPLUS", &["NUM+"]),
+    (79, "KP1", &["NUM1"]),
+    (80, "KP2", &["NUM2"]),
+    (81, "KP3", &["NUM3"]),
+    (82, "KP0", &["NUM0"]),
+    (83, "KPDOT", &["NUM."]),
+    (85, "ZENKAKUHANKAKU", &[]),
+    (86, "102ND", &[]),
+    (87, "F11", &[]),
+    (88, "F12", &[]),
+    (89, "RO", &[]),
+    (90, "KATAKANA", &[]),
+    (91, "HIRAGANA", &[]),
+    (92, "HENKAN", &[]),
+    (93, "KATAKANAHIRAGANA", &[]),
+    (94, "MUHENKAN", &[]),
+    (95, "KPJPCOMMA", &[]),
+    (96, "KPENTER", &["NUMENTER"]),
+    (97, "RIGHTCTRL", &["RCTRL"]),
+    (98, "KPSLASH", &["NUM/"]),
+    (99, "PrtSc", &["SYSRQ", "PRINTSCREEN"]),
+    (100, "RIGHTALT", &["RALT", "ALTGR"]),
+    (101, "LINEFEED", &[]),
+    (102, "HOME", &[]),
+    (103, "UP", &[]),
+    (104, "PGUP", &["PAGEUP"]),
+    (105, "LEFT", &[]),
+    (106, "RIGHT", &[]),
+    (107, "END", &[]),
+    (108, "DOWN", &[]),
+    (109, "PGDOWN", &["PAGEDOWN"]),
+    (110, "INS", &["INSERT"]),
+    (111, "DEL", &["DELETE"]),
+    (112, "MACRO", &[]),
+    (113, "MUTE", &["VOLUMEMUTE"]),
+    (114, "VOLUMEDOWN", &[]),
+    (115, "VOLUMEUP", &[]),
+    (116, "POWER", &[]),
+    (117, "KPEQUAL", &[]),
+    (118, "KPPLUSMINUS", &[]),
+    (119, "PAUSE", &[]),
+    (120, "SCALE", &[]),
+    (121, "KPCOMMA", &[]),
+    (122, "HANGEUL", &["HANGUEL"]),
+    (123, "HANJA", &[]),
+    (124, "YEN", &[]),
+    (125, "LEFTMETA", &["WIN", "LWIN", "META", "SUPER"]),
+    (126, "RIGHTMETA", &["RWIN"]),
+    (127, "COMPOSE", &["CONTEXTMENU"]),
+    (128, "STOP", &[]),
+    (129, "AGAIN", &[]),
+    (130, "PROPS", &[]),
+    (131, "UNDO", &[]),
+    (132, "FRONT", &[]),
+    (133, "COPY", &[]),
+    (134, "OPEN", &[]),
+    (135, "PASTE", &[]),
+    (136, "FIND", &[]),
+    (137, "CUT", &[]),
+    (138, "HELP", &[]),
+    (139, "MENU", &[]),
+    (140, "CALC", &["CALCULATOR"]),
+    (141, "SETUP", &[]),
+    (142, "SLEEP", &[]),
+    (143, "WAKEUP", &[]),
+    (144, "FILE", &[]),
+    (145, "SENDFILE", &[]),
+    (146, "DELETEFILE", &[]),
+    (147, "XFER", &[]),
+    (148, "PROG1", &[]),
+    (149, "PROG2", &[]),
+    (150, "WWW", &[]),
+    (151, "MSDOS", &[]),
+    (152, "COFFEE", &["SCREENLOCK"]),
+    (153, "ROTATE_DISPLAY", &["DIRECTION"]),
+    (154, "CYCLEWINDOWS", &[]),
+    (155, "MAIL", &["MAILCLIENT"]),
+    (156, "BOOKMARKS", &["BROWSERFAVORITES"]),
+    (157, "COMPUTER", &[]),
+    (158, "BACK", &["BROWSERBACK"]),
+    (159, "FORWARD", &["BROWSERFORWARD"]),
+    (160, "CLOSECD", &[]),
+    (161, "EJECTCD", &[]),
+    (162, "EJECTCLOSECD", &[]),
+    (163, "NEXTSONG", &["MEDIANEXT"]),
+    (164, "PLAYPAUSE", &["MEDIAPLAY"]),
+    (165, "PREVIOUSSONG", &["MEDIAPREV"]),
+    (166, "STOPCD", &["MEDIASTOP"]),
+    (167, "RECORD", &[]),
+    (168, "REWIND", &[]),
+    (169, "PHONE", &[]),
+    (170, "ISO", &[]),
+    (171, "CONFIG", &[]),
+    (172, "HOMEPAGE", &["BROWSERHOME"]),
+    (173, "REFRESH", &["BROWSERREFRESH"]),
+    (174, "EXIT", &[]),
+    (175, "MOVE", &[]),
+    (176, "EDIT", &[]),
+    (177, "SCROLLUP", &[]),
+    (178, "SCROLLDOWN", &[]),
+    (179, "KPLEFTPAREN", &[]),
+    (180, "KPRIGHTPAREN", &[]),
+    (181, "NEW", &[]),
+    (182, "REDO", &[]),
+    (183, "F13", &[]),
+    (184, "F14", &[]),
+    (185, "F15", &[]),
+    (186, "F16", &[]),
+    (187, "F17", &[]),
+    (188, "F18", &[]),
+    (189, "F19", &[]),
+    (190, "F20", &[]),
+    (191, "F21", &[]),
+    (192, "F22", &[]),
+    (193, "F23", &[]),
+    (194, "F24", &[]),
+    (200, "PLAYCD", &[]),
+    (201, "PAUSECD", &[]),
+    (202, "PROG3", &[]),
+    (203, "PROG4", &[]),
+    (204, "ALL_APPLICATIONS", &["DASHBOARD"]),
+    (205, "SUSPEND", &[]),
+    (206, "CLOSE", &[]),
+    (207, "PLAY", &[]),
+    (208, "FASTFORWARD", &[]),
+    (209, "BASSBOOST", &[]),
+    (210, "PRINT", &[]),
+    (211, "HP", &[]),
+    (212, "CAMERA", &[]),
+    (213, "SOUND", &[]),
+    (214, "QUESTION", &[]),
+    (215, "EMAIL", &[]),
+    (216, "CHAT", &[]),
+    (217, "SEARCH", &["BROWSERSEARCH"]),
+    (218, "CONNECT", &[]),
+    (219, "FINANCE", &[]),
+    (220, "SPORT", &[]),
+    (221, "SHOP", &[]),
+    (222, "ALTERASE", &[]),
+    (223, "CANCEL", &[]),
+    (224, "BRIGHTNESSDOWN", &[]),
+    (225, "BRIGHTNESSUP", &[]),
+    (226, "MEDIA", &[]),
+    (227, "SWITCHVIDEOMODE", &[]),
+    (228, "KBDILLUMTOGGLE", &[]),
+    (229, "KBDILLUMDOWN", &[]),
+    (230, "KBDILLUMUP", &[]),
+    (231, "SEND", &[]),
+    (232, "REPLY", &[]),
+    (233, "FORWARDMAIL", &[]),
+    (234, "SAVE", &[]),
+    (235, "DOCUMENTS", &[]),
+    (236, "BATTERY", &[]),
+    (237, "BLUETOOTH", &[]),
+    (238, "WLAN", &[]),
+    (239, "UWB", &[]),
+    (240, "UNKNOWN", &[]),
+    (241, "VIDEO_NEXT", &[]),
+    (242, "VIDEO_PREV", &[]),
+    (243, "BRIGHTNESS_CYCLE", &[]),
+    (244, "BRIGHTNESS_AUTO", &["BRIGHTNESS_ZERO"]),
+    (245, "DISPLAY_OFF", &[]),
+    (246, "WWAN", &["WIMAX"]),
+    (247, "RFKILL", &[]),
+    (248, "MICMUTE", &[]),
+    (256, "BTN_0", &["BTN_MISC"]),
+    (257, "BTN_1", &[]),
+    (258, "BTN_2", &[]),
+    (259, "BTN_3", &[]),
+    (260, "BTN_4", &[]),
+    (261, "BTN_5", &[]),
+    (262, "BTN_6", &[]),
+    (263, "BTN_7", &[]),
+    (264, "BTN_8", &[]),
+    (265, "BTN_9", &[]),
+    (272, "MOUSE-LEFT", &["BTN_LEFT", "BTN_MOUSE"]),
+    (273, "MOUSE-RIGHT", &["BTN_RIGHT"]),
+    (274, "MOUSE-MIDDLE", &["BTN_MIDDLE"]),
+    (275, "BTN_SIDE", &[]),
+    (276, "BTN_EXTRA", &[]),
+    (277, "BTN_FORWARD", &[]),
+    (278, "BTN_BACK", &[]),
+    (279, "BTN_TASK", &[]),
+    (288, "BTN_TRIGGER", &["BTN_JOYSTICK"]),
+    (289, "BTN_THUMB", &[]),
+    (290, "BTN_THUMB2", &[]),
+    (291, "BTN_TOP", &[]),
+    (292, "BTN_TOP2", &[]),
+    (293, "BTN_PINKIE", &[]),
+    (294, "BTN_BASE", &[]),
+    (295, "BTN_BASE2", &[]),
+    (296, "BTN_BASE3", &[]),
+    (297, "BTN_BASE4", &[]),
+    (298, "BTN_BASE5", &[]),
+    (299, "BTN_BASE6", &[]),
+    (303, "BTN_DEAD", &[]),
+    (304, "BTN_SOUTH", &["BTN_GAMEPAD", "BTN_A"]),
+    (305, "BTN_EAST", &["BTN_B"]),
+    (306, "BTN_C", &[]),
+    (307, "BTN_NORTH", &["BTN_X"]),
+    (308, "BTN_WEST", &["BTN_Y"]),
+    (309, "BTN_Z", &[]),
+    (310, "BTN_TL", &[]),
+    (311, "BTN_TR", &[]),
+    (312, "BTN_TL2", &[]),
+    (313, "BTN_TR2", &[]),
+    (314, "BTN_SELECT", &[]),
+    (315, "BTN_START", &[]),
+    (316, "BTN_MODE", &[]),
+    (317, "BTN_THUMBL", &[]),
+    (318, "BTN_THUMBR", &[]),
+    (320, "BTN_TOOL_PEN", &["BTN_DIGI"]),
+    (321, "BTN_TOOL_RUBBER", &[]),
+    (322, "BTN_TOOL_BRUSH", &[]),
+    (323, "BTN_TOOL_PENCIL", &[]),
+    (324, "BTN_TOOL_AIRBRUSH", &[]),
+    (325, "BTN_TOOL_FINGER", &[]),
+    (326, "BTN_TOOL_MOUSE", &[]),
+    (327, "BTN_TOOL_LENS", &[]),
+    (328, "BTN_TOOL_QUINTTAP", &[]),
+    (329, "BTN_STYLUS3", &[]),
+    (330, "BTN_TOUCH", &[]),
+    (331, "BTN_STYLUS", &[]),
+    (332, "BTN_STYLUS2", &[]),
+    (333, "BTN_TOOL_DOUBLETAP", &[]),
+    (334, "BTN_TOOL_TRIPLETAP", &[]),
+    (335, "BTN_TOOL_QUADTAP", &[]),
+    (336, "BTN_GEAR_DOWN", &["BTN_WHEEL"]),
+    (337, "BTN_GEAR_UP", &[]),
+    (352, "OK", &[]),
+    (353, "SELECT", &[]),
+    (354, "GOTO", &[]),
+    (355, "CLEAR", &[]),
+    (356, "POWER2", &[]),
+    (357, "OPTION", &[]),
+    (358, "INFO", &[]),
+    (359, "TIME", &[]),
+    (360, "VENDOR", &[]),
+    (361, "ARCHIVE", &[]),
+    (362, "PROGRAM", &[]),
+    (363, "CHANNEL", &[]),
+    (364, "FAVORITES", &[]),
+    (365, "EPG", &[]),
+    (366, "PVR", &[]),
+    (367, "MHP", &[]),
+    (368, "LANGUAGE", &[]),
+    (369, "TITLE", &[]),
+    (370, "SUBTITLE", &[]),
+    (371, "ANGLE", &[]),
+    (372, "FULL_SCREEN", &["ZOOM"]),
+    (373, "MODE", &[]),
+    (374, "KEYBOARD", &[]),
+    (375, "ASPECT_RATIO", &["SCREEN"]),
+    (376, "PC", &[]),
+    (377, "TV", &[]),
+    (378, "TV2", &[]),
+    (379, "VCR", &[]),
+    (380, "VCR2", &[]),
+    (381, "SAT", &[]),
+    (382, "SAT2", &[]),
+    (383, "CD", &[]),
+    (384, "TAPE", &[]),
+    (385, "RADIO", &[]),
+    (386, "TUNER", &[]),
+    (387, "PLAYER", &[]),
+    (388, "TEXT", &[]),
+    (389, "DVD", &[]),
+    (390, "AUX", &[]),
+    (391, "MP3", &[]),
+    (392, "AUDIO", &[]),
+    (393, "VIDEO", &[]),
+    (394, "DIRECTORY", &[]),
+    (395, "LIST", &[]),
+    (396, "MEMO", &[]),
+    (397, "CALENDAR", &[]),
+    (398, "RED", &[]),
+    (399, "GREEN", &[]),
+    (400, "YELLOW", &[]),
+    (401, "BLUE", &[]),
+    (402, "CHANNELUP", &[]),
+    (403, "CHANNELDOWN", &[]),
+    (404, "FIRST", &[]),
+    (405, "LAST", &[]),
+    (406, "AB", &[]),
+    (407, "NEXT", &[]),
+    (408, "RESTART", &[]),
+    (409, "SLOW", &[]),
+    (410, "SHUFFLE", &[]),
+    (411, "BREAK", &[]),
+    (412, "PREVIOUS", &[]),
+    (413, "DIGITS", &[]),
+    (414, "TEEN", &[]),
+    (415, "TWEN", &[]),
+    (416, "VIDEOPHONE", &[]),
+    (417, "GAMES", &[]),
+    (418, "ZOOMIN", &[]),
+    (419, "ZOOMOUT", &[]),
+    (420, "ZOOMRESET", &[]),
+    (421, "WORDPROCESSOR", &[]),
+    (422, "EDITOR", &[]),
+    (423, "SPREADSHEET", &[]),
+    (424, "GRAPHICSEDITOR", &[]),
+    (425, "PRESENTATION", &[]),
+    (426, "DATABASE", &[]),
+    (427, "NEWS", &[]),
+    (428, "VOICEMAIL", &[]),
+    (429, "ADDRESSBOOK", &[]),
+    (430, "MESSENGER", &[]),
+    (431, "DISPLAYTOGGLE", &["BRIGHTNESS_TOGGLE"]),
+    (432, "SPELLCHECK", &[]),
+    (433, "LOGOFF", &[]),
+    (434, "DOLLAR", &[]),
+    (435, "EURO", &[]),
+    (436, "FRAMEBACK", &[]),
+    (437, "FRAMEFORWARD", &[]),
+    (438, "CONTEXT_MENU", &[]),
+    (439, "MEDIA_REPEAT", &[]),
+    (440, "10CHANNELSUP", &[]),
+    (441, "10CHANNELSDOWN", &[]),
+    (442, "IMAGES", &[]),
+    (444, "NOTIFICATION_CENTER", &[]),
+    (445, "PICKUP_PHONE", &[]),
+    (446, "HANGUP_PHONE", &[]),
+    (447, "LINK_PHONE", &[]),
+    (448, "DEL_EOL", &[]),
+    (449, "DEL_EOS", &[]),
+    (450, "INS_LINE", &[]),
+    (451, "DEL_LINE", &[]),
+    (464, "FN", &[]),
+    (465, "FN_ESC", &[]),
+    (466, "FN_F1", &[]),
+    (467, "FN_F2", &[]),
+    (468, "FN_F3", &[]),
+    (469, "FN_F4", &[]),
+    (470, "FN_F5", &[]),
+    (471, "FN_F6", &[]),
+    (472, "FN_F7", &[]),
+    (473, "FN_F8", &[]),
+    (474, "FN_F9", &[]),
+    (475, "FN_F10", &[]),
+    (476, "FN_F11", &[]),
+    (477, "FN_F12", &[]),
+    (478, "FN_1", &[]),
+    (479, "FN_2", &[]),
+    (480, "FN_D", &[]),
+    (481, "FN_E", &[]),
+    (482, "FN_F", &[]),
+    (483, "FN_S", &[]),
+    (484, "FN_B", &[]),
+    (485, "FN_RIGHT_SHIFT", &[]),
+    (497, "BRL_DOT1", &[]),
+    (498, "BRL_DOT2", &[]),
+    (499, "BRL_DOT3", &[]),
+    (500, "BRL_DOT4", &[]),
+    (501, "BRL_DOT5", &[]),
+    (502, "BRL_DOT6", &[]),
+    (503, "BRL_DOT7", &[]),
+    (504, "BRL_DOT8", &[]),
+    (505, "BRL_DOT9", &[]),
+    (506, "BRL_DOT10", &[]),
+    (512, "NUMERIC_0", &[]),
+    (513, "NUMERIC_1", &[]),
+    (514, "NUMERIC_2", &[]),
+    (515, "NUMERIC_3", &[]),
+    (516, "NUMERIC_4", &[]),
+    (517, "NUMERIC_5", &[]),
+    (518, "NUMERIC_6", &[]),
+    (519, "NUMERIC_7", &[]),
+    (520, "NUMERIC_8", &[]),
+    (521, "NUMERIC_9", &[]),
+    (522, "NUMERIC_STAR", &[]),
+    (523, "NUMERIC_POUND", &[]),
+    (524, "NUMERIC_A", &[]),
+    (525, "NUMERIC_B", &[]),
+    (526, "NUMERIC_C", &[]),
+    (527, "NUMERIC_D", &[]),
+    (528, "CAMERA_FOCUS", &[]),
+    (529, "WPS_BUTTON", &[]),
+    (530, "TOUCHPAD_TOGGLE", &[]),
+    (531, "TOUCHPAD_ON", &[]),
+    (532, "TOUCHPAD_OFF", &[]),
+    (533, "CAMERA_ZOOMIN", &[]),
+    (534, "CAMERA_ZOOMOUT", &[]),
+    (535, "CAMERA_UP", &[]),
+    (536, "CAMERA_DOWN", &[]),
+    (537, "CAMERA_LEFT", &[]),
+    (538, "CAMERA_RIGHT", &[]),
+    (539, "ATTENDANT_ON", &[]),
+    (540, "ATTENDANT_OFF", &[]),
+    (541, "ATTENDANT_TOGGLE", &[]),
+    (542, "LIGHTS_TOGGLE", &[]),
+    (544, "BTN_DPAD_UP", &[]),
+    (545, "BTN_DPAD_DOWN", &[]),
+    (546, "BTN_DPAD_LEFT", &[]),
+    (547, "BTN_DPAD_RIGHT", &[]),
+    (560, "ALS_TOGGLE", &[]),
+    (561, "ROTATE_LOCK_TOGGLE", &[]),
+    (562, "REFRESH_RATE_TOGGLE", &[]),
+    (576, "BUTTONCONFIG", &[]),
+    (577, "TASKMANAGER", &[]),
+    (578, "JOURNAL", &[]),
+    (579, "CONTROLPANEL", &[]),
+    (580, "APPSELECT", &[]),
+    (581, "SCREENSAVER", &[]),
+    (582, "VOICECOMMAND", &[]),
+    (583, "ASSISTANT", &[]),
+    (584, "KBD_LAYOUT_NEXT", &[]),
+    (585, "EMOJI_PICKER", &[]),
+    (586, "DICTATE", &[]),
+    (592, "BRIGHTNESS_MIN", &[]),
+    (593, "BRIGHTNESS_MAX", &[]),
+    (608, "KBDINPUTASSIST_PREV", &[]),
+    (609, "KBDINPUTASSIST_NEXT", &[]),
+    (610, "KBDINPUTASSIST_PREVGROUP", &[]),
+    (611, "KBDINPUTASSIST_NEXTGROUP", &[]),
+    (612, "KBDINPUTASSIST_ACCEPT", &[]),
+    (613, "KBDINPUTASSIST_CANCEL", &[]),
+    (614, "RIGHT_UP", &[]),
+    (615, "RIGHT_DOWN", &[]),
+    (616, "LEFT_UP", &[]),
+    (617, "LEFT_DOWN", &[]),
+    (618, "ROOT_MENU", &[]),
+    (619, "MEDIA_TOP_MENU", &[]),
+    (620, "NUMERIC_11", &[]),
+    (621, "NUMERIC_12", &[]),
+    (622, "AUDIO_DESC", &[]),
+    (623, "3D_MODE", &[]),
+    (624, "NEXT_FAVORITE", &[]),
+    (625, "STOP_RECORD", &[]),
+    (626, "PAUSE_RECORD", &[]),
+    (627, "VOD", &[]),
+    (628, "UNMUTE", &[]),
+    (629, "FASTREVERSE", &[]),
+    (630, "SLOWREVERSE", &[]),
+    (631, "DATA", &[]),
+    (632, "ONSCREEN_KEYBOARD", &[]),
+    (633, "PRIVACY_SCREEN_TOGGLE", &[]),
+    (634, "SELECTIVE_SCREENSHOT", &[]),
+    (635, "NEXT_ELEMENT", &[]),
+    (636, "PREVIOUS_ELEMENT", &[]),
+    (637, "AUTOPILOT_ENGAGE_TOGGLE", &[]),
+    (638, "MARK_WAYPOINT", &[]),
+    (639, "SOS", &[]),
+    (640, "NAV_CHART", &[]),
+    (641, "FISHING_CHART", &[]),
+    (642, "SINGLE_RANGE_RADAR", &[]),
+    (643, "DUAL_RANGE_RADAR", &[]),
+    (644, "RADAR_OVERLAY", &[]),
+    (645, "TRADITIONAL_SONAR", &[]),
+    (646, "CLEARVU_SONAR", &[]),
+    (647, "SIDEVU_SONAR", &[]),
+    (648, "NAV_INFO", &[]),
+    (649, "BRIGHTNESS_MENU", &[]),
+    (656, "MACRO1", &[]),
+    (657, "MACRO2", &[]),
+    (658, "MACRO3", &[]),
+    (659, "MACRO4", &[]),
+    (660, "MACRO5", &[]),
+    (661, "MACRO6", &[]),
+    (662, "MACRO7", &[]),
+    (663, "MACRO8", &[]),
+    (664, "MACRO9", &[]),
+    (665, "MACRO10", &[]),
+    (666, "MACRO11", &[]),
+    (667, "MACRO12", &[]),
+    (668, "MACRO13", &[]),
+    (669, "MACRO14", &[]),
+    (670, "MACRO15", &[]),
+    (671, "MACRO16", &[]),
+    (672, "MACRO17", &[]),
+    (673, "MACRO18", &[]),
+    (674, "MACRO19", &[]),
+    (675, "MACRO20", &[]),
+    (676, "MACRO21", &[]),
+    (677, "MACRO22", &[]),
+    (678, "MACRO23", &[]),
+    (679, "MACRO24", &[]),
+    (680, "MACRO25", &[]),
+    (681, "MACRO26", &[]),
+    (682, "MACRO27", &[]),
+    (683, "MACRO28", &[]),
+    (684, "MACRO29", &[]),
+    (685, "MACRO30", &[]),
+    (688, "MACRO_RECORD_START", &[]),
+    (689, "MACRO_RECORD_STOP", &[]),
+    (690, "MACRO_PRESET_CYCLE", &[]),
+    (691, "MACRO_PRESET1", &[]),
+    (692, "MACRO_PRESET2", &[]),
+    (693, "MACRO_PRESET3", &[]),
+    (696, "KBD_LCD_MENU1", &[]),
+    (697, "KBD_LCD_MENU2", &[]),
+    (698, "KBD_LCD_MENU3", &[]),
+    (699, "KBD_LCD_MENU4", &[]),
+    (700, "KBD_LCD_MENU5", &[]),
+    (704, "BTN_TRIGGER_HAPPY1", &["BTN_TRIGGER_HAPPY"]),
+    (705, "BTN_TRIGGER_HAPPY2", &[]),
+    (706, "BTN_TRIGGER_HAPPY3", &[]),
+    (707, "BTN_TRIGGER_HAPPY4", &[]),
+    (708, "BTN_TRIGGER_HAPPY5", &[]),
+    (709, "BTN_TRIGGER_HAPPY6", &[]),
+    (710, "BTN_TRIGGER_HAPPY7", &[]),
+    (711, "BTN_TRIGGER_HAPPY8", &[]),
+    (712, "BTN_TRIGGER_HAPPY9", &[]),
+    (713, "BTN_TRIGGER_HAPPY10", &[]),
+    (714, "BTN_TRIGGER_HAPPY11", &[]),
+    (715, "BTN_TRIGGER_HAPPY12", &[]),
+    (716, "BTN_TRIGGER_HAPPY13", &[]),
+    (717, "BTN_TRIGGER_HAPPY14", &[]),
+    (718, "BTN_TRIGGER_HAPPY15", &[]),
+    (719, "BTN_TRIGGER_HAPPY16", &[]),
+    (720, "BTN_TRIGGER_HAPPY17", &[]),
+    (721, "BTN_TRIGGER_HAPPY18", &[]),
+    (722, "BTN_TRIGGER_HAPPY19", &[]),
+    (723, "BTN_TRIGGER_HAPPY20", &[]),
+    (724, "BTN_TRIGGER_HAPPY21", &[]),
+    (725, "BTN_TRIGGER_HAPPY22", &[]),
+    (726, "BTN_TRIGGER_HAPPY23", &[]),
+    (727, "BTN_TRIGGER_HAPPY24", &[]),
+    (728, "BTN_TRIGGER_HAPPY25", &[]),
+    (729, "BTN_TRIGGER_HAPPY26", &[]),
+    (730, "BTN_TRIGGER_HAPPY27", &[]),
+    (731, "BTN_TRIGGER_HAPPY28", &[]),
+    (732, "BTN_TRIGGER_HAPPY29", &[]),
+    (733, "BTN_TRIGGER_HAPPY30", &[]),
+    (734, "BTN_TRIGGER_HAPPY31", &[]),
+    (735, "BTN_TRIGGER_HAPPY32", &[]),
+    (736, "BTN_TRIGGER_HAPPY33", &[]),
+    (737, "BTN_TRIGGER_HAPPY34", &[]),
+    (738, "BTN_TRIGGER_HAPPY35", &[]),
+    (739, "BTN_TRIGGER_HAPPY36", &[]),
+    (740, "BTN_TRIGGER_HAPPY37", &[]),
+    (741, "BTN_TRIGGER_HAPPY38", &[]),
+    (742, "BTN_TRIGGER_HAPPY39", &[]),
+    (743, "BTN_TRIGGER_HAPPY40", &[]),
+];
+
+// Names the listener produced before every key had a unique name (old name, new name).
+// CTRL, ALT and ENTER used to be sent for both the left/main key and the right/keypad key,
+// they are migrated to the left/main key. The digit row was shifted by one ("10" was the 0 key).
+static LEGACY_NAMES: &[(&str, &str)] = &[
+    ("CTRL", "LEFTCTRL"),
+    ("ALT", "LEFTALT"),
+    ("LSHIFT", "LEFTSHIFT"),
+    ("RSHIFT", "RIGHTSHIFT"),
+    ("WIN", "LEFTMETA"),
+    ("NUM*", "KPASTERISK"),
+    ("NUM/", "KPSLASH"),
+    ("10", "0"),
+    ("0", "-"),
+    ("-", "="),
+];
+
+// Legacy names that matched two physical keys, the migrated binding only keeps the first one
+pub const AMBIGUOUS_LEGACY_NAMES: &[&str] = &["CTRL", "ALT", "ENTER"];
+
+// Name (uppercase) -> code, including aliases. The first entry wins on duplicate names.
+fn name_map() -> &'static HashMap<String, u16> {
+    static MAP: OnceLock<HashMap<String, u16>> = OnceLock::new();
+    MAP.get_or_init(|| {
+        let mut map = HashMap::with_capacity(KEYS.len() * 2);
+        for &(code, name, aliases) in KEYS {
+            map.entry(name.to_uppercase()).or_insert(code);
+            for alias in aliases {
+                map.entry(alias.to_uppercase()).or_insert(code);
+            }
+        }
+        map
+    })
+}
+
+// Canonical name of a key code, None if the code is not in the catalogue
+pub fn code_name(code: u16) -> Option<&'static str> {
+    KEYS.binary_search_by_key(&code, |&(c, _, _)| c)
+        .ok()
+        .map(|index| KEYS[index].1)
+}
+
+// Converts a key code to its canonical name, unknown codes become "KEY_<n>"
+pub fn key_to_string(code: u16) -> String {
+    match code_name(code) {
+        Some(name) => name.to_string(),
+        None => format!("KEY_{}", code),
+    }
+}
+
+// Converts a key name to its code. Case-insensitive, accepts canonical names, aliases,
+// header names with "KEY_" prefix ("KEY_F13") and the numeric fallback ("KEY_84").
+pub fn string_to_key(name: &str) -> Option<u16> {
+    let upper = name.trim().to_uppercase();
+    if let Some(&code) = name_map().get(&upper) {
+        return Some(code);
+    }
+
+    let stripped = upper.strip_prefix("KEY_")?;
+    if let Some(&code) = name_map().get(stripped) {
+        return Some(code);
+    }
+    stripped.parse::<u16>().ok()
+}
+
+// Chords are keys of one device held down together, named like "A+S" with the keys ordered by code
+pub const CHORD_SEPARATOR: char = '+';
+
+// Name of the chord formed by these keys, independent of the order they were pressed in
+pub fn chord_name<S: AsRef<str>>(keys: &[S]) -> String {
+    let mut keys: Vec<&str> = keys.iter().map(|key| key.as_ref()).collect();
+    keys.sort_by_key(|key| string_to_key(key).unwrap_or(u16::MAX));
+    keys.join(&CHORD_SEPARATOR.to_string())
+}
+
+// Keys of a chord name, None if the name is no chord (a single key, "NUM+" or "ABS_Z+")
+pub fn chord_keys(name: &str) -> Option<Vec<&str>> {
+    let keys: Vec<&str> = name.split(CHORD_SEPARATOR).collect();
+    if keys.len() < 2 || keys.iter().any(|key| string_to_key(key).is_none()) {
+        return None;
+    }
+    Some(keys)
+}
+
+// Sequences are keys tapped one after the other, like a leader key in vim: "G>C" is G, then C
+pub const SEQUENCE_SEPARATOR: char = '>';
+
+pub fn sequence_name<S: AsRef<str>>(keys: &[S]) -> String {
+    keys.iter().map(|key| key.as_ref()).collect::<Vec<_>>().join(&SEQUENCE_SEPARATOR.to_string())
+}
+
+// Keys of a sequence name in the order they are tapped, None if the name is no sequence
+pub fn sequence_keys(name: &str) -> Option<Vec<&str>> {
+    let keys: Vec<&str> = name.split(SEQUENCE_SEPARATOR).collect();
+    if keys.len() < 2 || keys.iter().any(|key| string_to_key(key).is_none()) {
+        return None;
+    }
+    Some(keys)
+}
+
+// All catalogue entries as (code, canonical name, aliases), sorted by code
+pub fn all_keys() -> impl Iterator<Item = (u16, &'static str, &'static [&'static str])> {
+    KEYS.iter().copied()
+}
+
+// Maps a key name stored by an older version to the current canonical name.
+// Returns None if the name is still valid (or not a key name at all, e.g. "new").
+// Also resolves "KEY_<n>" fallbacks for codes that have a name in the catalogue by now.
+pub fn migrate_legacy_name(name: &str) -> Option<String> {
+    if let Some(&(_, new_name)) = LEGACY_NAMES.iter().find(|&&(old, _)| old == name) {
+        return Some(new_name.to_string());
+    }
+
+    let code = name.strip_prefix("KEY_")?.parse::<u16>().ok()?;
+    code_name(code).map(|new_name| new_name.to_string())
+}
+
+// Relative axes (EV_REL) that can trigger macros
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_DIAL: u16 = 0x07;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_MISC: u16 = 0x09;
+
+// (code, name for positive steps, name for negative steps)
+// REL_X/REL_Y (mouse movement) and the hi-res wheel duplicates are not triggers.
+static REL_TRIGGERS: &[(u16, &str, &str)] = &[
+    (REL_HWHEEL, "WHEEL-RIGHT", "WHEEL-LEFT"),
+    (REL_DIAL, "KNOB-CW", "KNOB-CCW"),
+    (REL_WHEEL, "WHEEL-UP", "WHEEL-DOWN"),
+    (REL_MISC, "MISC-UP", "MISC-DOWN"),
+];
+
+// Trigger name of a relative axis movement, None for axes that are no triggers
+pub fn rel_to_string(code: u16, value: i32) -> Option<&'static str> {
+    let &(_, positive, negative) = REL_TRIGGERS.iter().find(|&&(c, _, _)| c == code)?;
+    match value {
+        0 => None,
+        v if v > 0 => Some(positive),
+        _ => Some(negative),
+    }
+}
+
+// Whether a device with these relative axes has a wheel or knob
+pub fn is_rel_trigger(code: u16) -> bool {
+    REL_TRIGGERS.iter().any(|&(c, _, _)| c == code)
+}
+
+// Absolute axes (EV_ABS) of gamepads and joysticks, every direction is a trigger.
+// (code, name for the positive direction, name for the negative direction)
+static ABS_TRIGGERS: &[(u16, &str, &str)] = &[
+    (0x00, "LEFTSTICK-RIGHT", "LEFTSTICK-LEFT"),
+    (0x01, "LEFTSTICK-DOWN", "LEFTSTICK-UP"),
+    (0x02, "ABS_Z+", "ABS_Z-"),
+    (0x03, "RIGHTSTICK-RIGHT", "RIGHTSTICK-LEFT"),
+    (0x04, "RIGHTSTICK-DOWN", "RIGHTSTICK-UP"),
+    (0x05, "ABS_RZ+", "ABS_RZ-"),
+    (0x06, "THROTTLE+", "THROTTLE-"),
+    (0x07, "RUDDER+", "RUDDER-"),
+    (0x08, "ABS_WHEEL+", "ABS_WHEEL-"),
+    (0x09, "GAS+", "GAS-"),
+    (0x0a, "BRAKE+", "BRAKE-"),
+    (0x10, "HAT-RIGHT", "HAT-LEFT"),
+    (0x11, "HAT-DOWN", "HAT-UP"),
+    (0x12, "HAT1-RIGHT", "HAT1-LEFT"),
+    (0x13, "HAT1-DOWN", "HAT1-UP"),
+    (0x14, "HAT2-RIGHT", "HAT2-LEFT"),
+    (0x15, "HAT2-DOWN", "HAT2-UP"),
+    (0x16, "HAT3-RIGHT", "HAT3-LEFT"),
+    (0x17, "HAT3-DOWN", "HAT3-UP"),
+];
+
+// Trigger name of an axis direction, None for axes that are no triggers (multitouch, ABS_MISC, ...)
+pub fn abs_to_string(code: u16, positive: bool) -> Option<&'static str> {
+    let &(_, pos, neg) = ABS_TRIGGERS.iter().find(|&&(c, _, _)| c == code)?;
+    Some(if positive { pos } else { neg })
+}
+
+// Whether an axis can trigger macros
+pub fn is_abs_trigger(code: u16) -> bool {
+    ABS_TRIGGERS.iter().any(|&(c, _, _)| c == code)
+}
+
+// LEDs (EV_LED) by name, for led("caps", true)
+static LEDS: &[(u16, &str, &[&str])] = &[
+    (0x00, "NUM", &["NUMLOCK"]),
+    (0x01, "CAPS", &["CAPSLOCK"]),
+    (0x02, "SCROLL", &["SCROLLLOCK"]),
+    (0x03, "COMPOSE", &[]),
+    (0x04, "KANA", &[]),
+    (0x05, "SLEEP", &[]),
+    (0x06, "SUSPEND", &[]),
+    (0x07, "MUTE", &[]),
+    (0x08, "MISC", &[]),
+    (0x09, "MAIL", &[]),
+    (0x0a, "CHARGING", &[]),
+];
+
+// LED code of a name, case-insensitive
+pub fn string_to_led(name: &str) -> Option<u16> {
+    let upper = name.trim().to_uppercase();
+    LEDS.iter()
+        .find(|&&(_, led, aliases)| led == upper || aliases.contains(&upper.as_str()))
+        .map(|&(code, _, _)| code)
+}
@@ -4,41 +4,137 @@ use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
-use std::os::unix::io::AsRawFd;
-use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 use serde_json;
-use std::sync::atomic::{AtomicBool, Ordering};
-use ctrlc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub use crate::event_handler;
+use crate::key_codes::{self, key_to_string};
+use crate::evdev::{self, EVIOCGRAB, EV_SYN, EV_KEY, EV_REL, EV_ABS, EV_MSC, EV_LED, MSC_SCAN};
+use crate::hotplug::{self, HotplugEvent};
+use crate::poller::{self, WakeFd};
+use crate::input_source::{EventReader, EvdevSource, InputEvent, InputSource, SourceEvent};
+use crate::recorder::Recorder;
+use crate::uinput::{VirtualDevice, VIRTUAL_DEVICE_PREFIX};
 
-// Structure for Linux input events
-#[repr(C)]
-struct InputEvent {
-    tv_sec: libc::time_t,
-    tv_usec: libc::suseconds_t,
-    type_: u16,
-    code: u16,
-    value: i32,
-}
-
-// Constants for event types
-const EV_KEY: u16 = 1;
+// Constants for key event values
 const KEY_RELEASE: i32 = 0;
 const KEY_PRESS: i32 = 1;
 const KEY_REPEAT: i32 = 2;
 const INPUT_DEVICES_PATH: &str = "/dev/input";
 
 // Structure for device information
-#[derive(Clone, Serialize, Deserialize)]
+// event_num and device_path change between boots, the remaining fields identify the device.
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub event_num: usize,
     pub device_path: String,
     pub device_name: String,
     pub usb_port: String,
+    #[serde(default)]
+    pub bus_type: u16,    // EVIOCGID
+    #[serde(default)]
+    pub vendor_id: u16,
+    #[serde(default)]
+    pub product_id: u16,
+    #[serde(default)]
+    pub version: u16,
+    #[serde(default)]
+    pub phys: String,     // EVIOCGPHYS, physical path like "usb-0000:00:14.0-2/input0"
+    #[serde(default)]
+    pub uniq: String,     // EVIOCGUNIQ, serial number if the device has one
+    #[serde(default)]
+    pub capabilities: DeviceCapabilities,
+}
+
+// What a device can report, read with EVIOCGBIT
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceCapabilities {
+    pub keys: bool,            // KEY_* codes: keyboard, keypad, media keys
+    pub letter_keys: bool,     // KEY_A..KEY_Z: a full keyboard
+    pub buttons: bool,         // BTN_* codes: mouse, joystick and gamepad buttons
+    pub gamepad_buttons: bool, // BTN_JOYSTICK..BTN_THUMBR
+    pub relative: bool,        // EV_REL: mouse movement, wheels, knobs
+    #[serde(default)]
+    pub wheels: bool,          // Scroll wheels or rotary knobs that can trigger macros
+    pub absolute: bool,        // EV_ABS: touchpads, sticks, hats
+    pub leds: bool,            // EV_LED: caps lock, num lock, ...
+}
+
+// Rough device class derived from the capabilities
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Keyboard,
+    Keypad,   // keys but no letters: numpads, macro pads, "Consumer Control"
+    Gamepad,
+    Pointer,  // mice, touchpads, tablets
+    Other,
+}
+
+impl Display for DeviceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceKind::Keyboard => write!(f, "keyboard"),
+            DeviceKind::Keypad => write!(f, "keypad"),
+            DeviceKind::Gamepad => write!(f, "gamepad"),
+            DeviceKind::Pointer => write!(f, "pointer"),
+            DeviceKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl DeviceCapabilities {
+    // Reads the capability bitmaps of an opened device
+    pub(crate) fn read(fd: RawFd) -> io::Result<Self> {
+        let types = evdev::read_bits(fd, 0, evdev::EV_MAX)?;
+        let mut capabilities = DeviceCapabilities {
+            relative: types.has(EV_REL),
+            absolute: types.has(EV_ABS),
+            leds: types.has(EV_LED),
+            ..Default::default()
+        };
+        
+        if types.has(EV_KEY) {
+            let keys = evdev::read_bits(fd, EV_KEY, evdev::KEY_MAX)?;
+            // Buttons: BTN_MISC (0x100) up to KEY_OK (0x160), BTN_DPAD_* (0x220..0x223) and
+            // BTN_TRIGGER_HAPPY* (0x2c0..), everything else keys
+            capabilities.keys = keys.any(1..=0xff) || keys.any(0x160..=0x21f) || keys.any(0x224..=0x2bf);
+            capabilities.buttons = keys.any(0x100..=0x15f) || keys.any(0x220..=0x223) || keys.any(0x2c0..=evdev::KEY_MAX);
+            capabilities.letter_keys = [16, 30, 44].iter().all(|&code| keys.has(code)); // Q, A, Z
+            capabilities.gamepad_buttons = keys.any(0x120..=0x13e);
+        }
+        
+        if capabilities.relative {
+            let axes = evdev::read_bits(fd, EV_REL, evdev::REL_MAX)?;
+            capabilities.wheels = (0..=evdev::REL_MAX).any(|code| axes.has(code) && key_codes::is_rel_trigger(code));
+        }
+        
+        Ok(capabilities)
+    }
+    
+    pub fn kind(&self) -> DeviceKind {
+        if self.letter_keys {
+            DeviceKind::Keyboard
+        } else if self.gamepad_buttons {
+            DeviceKind::Gamepad
+        } else if self.keys || (self.wheels && !self.buttons) {
+            DeviceKind::Keypad
+        } else if self.buttons && (self.relative || self.absolute) {
+            DeviceKind::Pointer
+        } else {
+            DeviceKind::Other
+        }
+    }
+    
+    // Whether the device sends anything that can trigger a macro
+    pub fn can_trigger(&self) -> bool {
+        self.keys || self.buttons || self.wheels
+    }
 }
 
 impl Debug for DeviceInfo {
@@ -48,50 +144,157 @@ impl Debug for DeviceInfo {
          .field("device_path", &self.device_path)
          .field("device_name", &self.device_name)
          .field("usb_port", &self.usb_port)
+         .field("id", &format_args!("{:04x}:{:04x}:{:04x} v{:04x}", self.bus_type, self.vendor_id, self.product_id, self.version))
+         .field("phys", &self.phys)
+         .field("uniq", &self.uniq)
+         .field("kind", &self.kind())
          .finish()
     }
 }
 
-// Global status variables
-static MONITOR_RUNNING: AtomicBool = AtomicBool::new(false);
-static GRABBED_FDS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+impl DeviceInfo {
+    // Whether bus type, vendor and product were read from the device (keyb.json of older versions has none)
+    fn has_id(&self) -> bool {
+        self.bus_type != 0 || self.vendor_id != 0 || self.product_id != 0
+    }
+    
+    // Identity for key bindings: the IDs plus the serial number or, without one, the physical path.
+    // Tells identical devices apart, which share their name. Devices without IDs (e.g. a recording) use their name.
+    pub fn device_id(&self) -> String {
+        if !self.has_id() {
+            return self.device_name.clone();
+        }
+        let location = if self.uniq.is_empty() { &self.phys } else { &self.uniq };
+        format!("{}{}", self.model_id(), location)
+    }
+    
+    // Bus type, vendor and product, the part of device_id that does not depend on the port
+    fn model_id(&self) -> String {
+        format!("{:04x}:{:04x}:{:04x}:", self.bus_type, self.vendor_id, self.product_id)
+    }
+    
+    // Whether a key binding saved for `saved_id` (device_id when it was assigned) belongs to this device.
+    // A serial number has to match. Most devices have none, their id ends in the physical path, which changes
+    // with the USB port or hub: it only tells identical devices apart that are connected at the same time.
+    // If none of `connected_ids` has the saved path any more, bus, vendor and product are enough.
+    pub fn is_bound_to(&self, saved_id: &str, connected_ids: &[String]) -> bool {
+        if saved_id == self.device_id() {
+            return true;
+        }
+        self.has_id()
+            && self.uniq.is_empty()
+            && saved_id.starts_with(&self.model_id())
+            && !connected_ids.iter().any(|id| id == saved_id)
+    }
+    
+    pub fn kind(&self) -> DeviceKind {
+        self.capabilities.kind()
+    }
+    
+    // Virtual devices created by MacroKeyB itself
+    pub fn is_virtual(&self) -> bool {
+        self.device_name.starts_with(VIRTUAL_DEVICE_PREFIX)
+    }
+}
+
+// Status of one blocked device
+struct MonitorState {
+    id: u64,                   // Tells monitors of the same device path apart
+    device: DeviceInfo,
+    running: Arc<AtomicBool>,  // Cleared to stop the monitor of this device
+    wake: Arc<WakeFd>,         // Wakes the monitor thread blocked in poll
+    grabbed_fd: Option<i32>,   // File descriptor if EVIOCGRAB succeeded
+    led_fd: Option<RawFd>,     // File descriptor to switch the LEDs of the device, None if it has none
+}
 
-// Reusable event data for better performance
-struct EventReader {
-    reader: BufReader<File>,
-    buffer: [u8; std::mem::size_of::<InputEvent>()],
+// Events of one frame (up to SYN_REPORT) that selective grab passes on.
+// The scan code (MSC_SCAN) before a key follows the decision for that key.
+#[derive(Default)]
+struct ForwardFrame {
+    events: Vec<InputEvent>,
+    scan: Option<InputEvent>, // Scan code waiting for its key
+    passed_on: bool,          // The frame has an event besides scan codes and other EV_MSC
 }
 
-impl EventReader {
-    fn new(file: File) -> io::Result<Self> {
-        // Set non-blocking mode
-        let fd = file.as_raw_fd();
-        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL, 0) };
-        unsafe { 
-            if libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
-                return Err(io::Error::last_os_error());
-            }
+impl ForwardFrame {
+    // Adds an event of the frame, `forward` tells whether it is passed on
+    fn push(&mut self, event: InputEvent, forward: bool) {
+        match (event.type_, event.code) {
+            (EV_MSC, MSC_SCAN) => self.scan = Some(event),
+            (EV_MSC, _) => self.events.extend(Some(event).filter(|_| forward)),
+            (type_, _) => {
+                let scan = self.scan.take().filter(|_| type_ == EV_KEY);
+                if forward {
+                    self.events.extend(scan);
+                    self.events.push(event);
+                    self.passed_on = true;
+                }
+            },
         }
-        
-        Ok(EventReader {
-            reader: BufReader::new(file),
-            buffer: [0u8; std::mem::size_of::<InputEvent>()],
-        })
     }
     
-    fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
-        match self.reader.read_exact(&mut self.buffer) {
-            Ok(_) => {
-                let event = unsafe { std::ptr::read(self.buffer.as_ptr() as *const InputEvent) };
-                Ok(Some(event))
-            },
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
-            Err(e) => Err(e)
+    // End of the frame: the events to pass on followed by the SYN_REPORT, nothing if no event is passed on
+    fn finish(&mut self, syn: InputEvent) -> Vec<InputEvent> {
+        let mut events = std::mem::take(&mut self.events);
+        let passed_on = std::mem::take(&mut self.passed_on);
+        self.scan = None;
+        if !passed_on {
+            return Vec::new();
         }
+        events.push(syn);
+        events
+    }
+}
+
+// Whether an event starts a trigger: key press or wheel/knob step
+fn is_trigger_press(event: &InputEvent) -> bool {
+    match event.type_ {
+        EV_KEY => event.value == KEY_PRESS,
+        EV_REL => key_codes::rel_to_string(event.code, event.value).is_some(),
+        _ => false,
+    }
+}
+
+// How long the detection keeps listening for other devices after the first key press
+const DETECTION_WINDOW: Duration = Duration::from_millis(1000);
+
+// Give up the detection after this long without input
+pub const DETECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Stops a running detect_input_devices from another thread
+#[derive(Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    wake: Arc<WakeFd>,   // Wakes the detection blocked in poll
+}
+
+impl CancelToken {
+    pub fn new() -> io::Result<Self> {
+        Ok(CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            wake: Arc::new(WakeFd::new()?),
+        })
+    }
+    
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.wake.wake();
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
     }
 }
 
+// Device that produced input during detection
+#[derive(Clone, Debug, Serialize)]
+pub struct DetectedDevice {
+    pub device: DeviceInfo,
+    pub presses: usize,   // Key presses and wheel steps during detection
+}
+
 // Key handling
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum KeyState {
     Down,
     Press,
@@ -111,78 +314,106 @@ impl Display for KeyState {
 pub struct KeyInfo {
     pub name: String,
     pub state: KeyState,
+    pub device: String, // Name of the device the key came from
+    pub device_id: String, // DeviceInfo::device_id of the device, differs between identical devices
+    pub delta: i32,     // Steps of a wheel or knob trigger ("WHEEL-UP", "KNOB-CW", ...), 0 for keys
 }
 
-// Helper function to convert key codes to readable text
-fn key_to_string(code: u16) -> String {
-    match code {
-        1 => "ESC".into(),
-        2..=11 => (code - 1).to_string(),
-        12 => "0".into(),
-        13 => "-".into(),
-        14 => "BACKSPACE".into(),
-        15 => "TAB".into(),
-        16..=25 => ["Q", "W", "E", "R", "T", "Y", "U", "I", "O", "P"][code as usize - 16].into(),
-        26 => "[".into(),
-        27 => "]".into(),
-        28 => "ENTER".into(),
-        29 => "CTRL".into(),
-        30..=38 => ["A", "S", "D", "F", "G", "H", "J", "K", "L"][code as usize - 30].into(),
-        39 => ";".into(),
-        40 => "'".into(),
-        41 => "`".into(),
-        42 => "LSHIFT".into(),
-        43 => "\\".into(),
-        44..=53 => ["Z", "X", "C", "V", "B", "N", "M", ",", ".", "/"][code as usize - 44].into(),
-        54 => "RSHIFT".into(),
-        55 => "NUM*".into(),
-        56 => "ALT".into(),
-        57 => "SPACE".into(),
-        58 => "CAPSLOCK".into(),
-        59..=68 => format!("F{}", code - 58),
-        87 => "F11".into(),
-        88 => "F12".into(),
-        96 => "ENTER".into(),
-        97 => "CTRL".into(),
-        98 => "NUM/".into(),
-        99 => "PrtSc".into(),
-        100 => "ALT".into(),
-        102 => "HOME".into(),
-        103 => "UP".into(),
-        104 => "PGUP".into(),
-        105 => "LEFT".into(),
-        106 => "RIGHT".into(),
-        107 => "END".into(),
-        108 => "DOWN".into(),
-        109 => "PGDOWN".into(),
-        110 => "INS".into(),
-        111 => "DEL".into(),
-        125 => "WIN".into(),
-        272 => "MOUSE-LEFT".into(),
-        273 => "MOUSE-RIGHT".into(),
-        274 => "MOUSE-MIDDLE".into(),
-        _ => format!("KEY_{}", code),
-    }
+// Input device plugged in or removed
+pub enum DeviceChange {
+    Connected(DeviceInfo),
+    Disconnected { device_path: String, device_name: String },
 }
 
 // Main instance for keyboard monitoring
 pub struct Instance {
     pub on_key: Arc<event_handler::EventHandler<KeyInfo>>,
+    pub on_device: Arc<event_handler::EventHandler<DeviceChange>>,
+    pub on_monitor_end: Arc<event_handler::EventHandler<DeviceInfo>>, // Monitor stopped or its device removed
+    monitors: Mutex<HashMap<String, MonitorState>>, // Device path -> running monitor
+    known_devices: Mutex<HashMap<String, String>>,  // Device path -> name, to name removed devices
+    recorders: Mutex<HashMap<String, Recorder>>,    // Device path -> recording of its events
+    trigger_filter: RwLock<Option<TriggerFilter>>,  // Selective grab, see set_trigger_filter
+    hotplug_started: AtomicBool,
+}
+
+// Handle of a running monitor, see Instance::start_monitor.
+// Stopping or dropping the handle stops the monitor and ungrabs the device, other monitors keep running.
+pub struct Monitor {
+    instance: &'static Instance,
+    device: DeviceInfo,
+    id: u64,
+    thread: Option<thread::JoinHandle<io::Result<()>>>,
 }
 
+impl Monitor {
+    pub fn device(&self) -> &DeviceInfo {
+        &self.device
+    }
+    
+    // False once the monitor was stopped or its device removed (already inside on_monitor_end)
+    pub fn is_running(&self) -> bool {
+        self.instance.monitors.lock()
+            .map(|monitors| monitors.get(&self.device.device_path).is_some_and(|state| state.id == self.id))
+            .unwrap_or(false)
+    }
+    
+    // Stops the monitor and waits until its thread has ended
+    pub fn stop(mut self) -> io::Result<()> {
+        self.instance.release_monitor(&self.device.device_path, self.id);
+        self.join()
+    }
+    
+    // Waits until the monitor ends by itself (device removed, recording finished)
+    pub fn wait(mut self) -> io::Result<()> {
+        self.join()
+    }
+    
+    fn join(&mut self) -> io::Result<()> {
+        let Some(thread) = self.thread.take() else { return Ok(()) };
+        
+        // Stopped from a macro running on the monitor thread itself, it ends after the macro
+        if thread.thread().id() == thread::current().id() {
+            return Ok(());
+        }
+        
+        thread.join().unwrap_or_else(|_| Err(io::Error::other("Monitor thread panicked")))
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.instance.release_monitor(&self.device.device_path, self.id);
+        if let Err(e) = self.join() {
+            eprintln!("Monitor of {} ended with an error: {}", self.device.device_path, e);
+        }
+    }
+}
+
+// Decides whether an input is used as a macro trigger (true) or passed on to the system (false)
+pub type TriggerFilter = Arc<dyn Fn(&KeyInfo) -> bool + Send + Sync>;
+
 impl Instance {
     pub fn new() -> &'static Self {
         static INSTANCE: OnceLock<Instance> = OnceLock::new();
         INSTANCE.get_or_init(|| { 
-            Self::admit_sudo();
             Instance { 
-                on_key: Arc::new(event_handler::EventHandler::new())
+                on_key: Arc::new(event_handler::EventHandler::new()),
+                on_device: Arc::new(event_handler::EventHandler::new()),
+                on_monitor_end: Arc::new(event_handler::EventHandler::new()),
+                monitors: Mutex::new(HashMap::new()),
+                known_devices: Mutex::new(HashMap::new()),
+                recorders: Mutex::new(HashMap::new()),
+                trigger_filter: RwLock::new(None),
+                hotplug_started: AtomicBool::new(false),
             }
         });
         INSTANCE.get().unwrap()
     }
 
-    fn admit_sudo() {
+    // Checks whether input devices can be read. Only needed for real devices,
+    // replayed recordings (ReplaySource) work without root.
+    pub fn admit_sudo() -> io::Result<()> {
         let has_root_access = OpenOptions::new()
             .read(true)
             .write(false)
@@ -190,8 +421,12 @@ impl Instance {
             .is_ok();
     
         if !has_root_access {
-            panic!("Keyboard Listener needs root rights to work correctly");
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Keyboard Listener needs root rights to work correctly"
+            ));
         }
+        Ok(())
     }
     
     // Device management functions
@@ -207,7 +442,8 @@ impl Instance {
                     if file_name.starts_with("event") {
                         if let Ok(event_num) = file_name.trim_start_matches("event").parse::<usize>() {
                             if let Some(device_info) = self.get_device_info(&path, event_num) {
-                                if device_info.usb_port.to_lowercase().contains("usb") { 
+                                // Only devices that can send keys or buttons, no matter how they are connected
+                                if device_info.capabilities.can_trigger() && !device_info.is_virtual() { 
                                     device_list.push(device_info);
                                 }
                             }
@@ -233,12 +469,124 @@ impl Instance {
         // Get USB port info
         let usb_port = self.extract_usb_info(event_num, &sysfs_path);
         
-        Some(DeviceInfo {
+        let mut device_info = DeviceInfo {
             event_num,
             device_path,
             device_name,
             usb_port,
-        })
+            bus_type: 0,
+            vendor_id: 0,
+            product_id: 0,
+            version: 0,
+            phys: String::new(),
+            uniq: String::new(),
+            capabilities: DeviceCapabilities::default(),
+        };
+        
+        // Stable identity from the device itself (requires read access)
+        if let Ok(file) = File::open(path) {
+            let fd = file.as_raw_fd();
+            if let Ok(id) = evdev::read_id(fd) {
+                device_info.bus_type = id.bustype;
+                device_info.vendor_id = id.vendor;
+                device_info.product_id = id.product;
+                device_info.version = id.version;
+            }
+            device_info.phys = evdev::read_phys(fd).unwrap_or_default();
+            device_info.uniq = evdev::read_uniq(fd).unwrap_or_default();
+            device_info.capabilities = DeviceCapabilities::read(fd).unwrap_or_default();
+        }
+        
+        if let Ok(mut known) = self.known_devices.lock() {
+            known.insert(device_info.device_path.clone(), device_info.device_name.clone());
+        }
+        
+        Some(device_info)
+    }
+    
+    // Starts a background thread that reports plugged in and removed devices through on_device.
+    // Only the first call starts the thread.
+    pub fn start_hotplug_monitor(&'static self) {
+        if self.hotplug_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        
+        thread::spawn(move || {
+            let result = hotplug::watch_input_dir(Path::new(INPUT_DEVICES_PATH), |event| {
+                match event {
+                    HotplugEvent::Added(path) => {
+                        let event_num = path.file_name()
+                            .and_then(|name| name.to_string_lossy().trim_start_matches("event").parse::<usize>().ok());
+                        let Some(event_num) = event_num else { return };
+                        
+                        if let Some(device_info) = self.get_device_info(&path, event_num).filter(|d| !d.is_virtual()) {
+                            println!("Input device connected: {} ({})", device_info.device_name, device_info.device_path);
+                            self.on_device.trigger(&DeviceChange::Connected(device_info));
+                        }
+                    },
+                    HotplugEvent::Removed(path) => {
+                        let device_path = path.to_string_lossy().into_owned();
+                        let device_name = self.known_devices.lock().ok()
+                            .and_then(|mut known| known.remove(&device_path))
+                            .unwrap_or_else(|| String::from("Unknown Device"));
+                        if device_name.starts_with(VIRTUAL_DEVICE_PREFIX) {
+                            return;
+                        }
+                        
+                        println!("Input device disconnected: {} ({})", device_name, device_path);
+                        self.on_device.trigger(&DeviceChange::Disconnected { device_path, device_name });
+                    },
+                }
+            });
+            
+            if let Err(e) = result {
+                eprintln!("Hotplug monitoring stopped: {}", e);
+            }
+            self.hotplug_started.store(false, Ordering::SeqCst);
+        });
+    }
+    
+    // Finds the currently connected devices for saved devices.
+    // Event numbers change between boots, so devices are matched by their properties:
+    // - bus type, vendor and product must match (name and USB port for entries without IDs)
+    // - the device name must match (one keyboard often has several event devices with the same IDs)
+    // Several matches are ranked by: same unique ID, same physical path, same event number as saved,
+    // lowest event number. Every connected device is used for at most one saved device.
+    pub fn resolve_saved_devices(&self, saved_devices: &[DeviceInfo]) -> Vec<Option<DeviceInfo>> {
+        let connected = self.get_device_list();
+        let mut taken: Vec<String> = Vec::new();
+        
+        saved_devices.iter().map(|saved| {
+            let best = connected.iter()
+                .filter(|dev| !taken.contains(&dev.device_path))
+                .filter(|dev| Self::is_same_device(saved, dev))
+                .min_by_key(|dev| (
+                    saved.uniq.is_empty() || dev.uniq != saved.uniq,
+                    dev.phys != saved.phys,
+                    dev.event_num != saved.event_num,
+                    dev.event_num,
+                ))
+                .cloned();
+            
+            if let Some(dev) = &best {
+                taken.push(dev.device_path.clone());
+            }
+            best
+        }).collect()
+    }
+    
+    fn is_same_device(saved: &DeviceInfo, dev: &DeviceInfo) -> bool {
+        if saved.device_name != dev.device_name {
+            return false;
+        }
+        
+        if saved.has_id() {
+            saved.bus_type == dev.bus_type
+                && saved.vendor_id == dev.vendor_id
+                && saved.product_id == dev.product_id
+        } else {
+            saved.usb_port == dev.usb_port
+        }
     }
     
     fn extract_usb_info(&self, event_num: usize, sysfs_path: &Path) -> String {
@@ -355,334 +703,483 @@ impl Instance {
         Ok(selected_devices)
     }
     
-pub fn auto_detect_input_device(&self, device_list: &[DeviceInfo]) -> io::Result<Option<DeviceInfo>> {
-    // Filter input devices if possible
-    let input_devices: Vec<DeviceInfo> = device_list.iter()
-        .filter(|d| d.device_name.to_lowercase().contains("keyboard"))
-        .cloned()
-        .collect();
-
-    let devices_to_check = if input_devices.is_empty() {
-        device_list.to_vec()
-    } else {
-        input_devices
-    }; 
-
-    // Flag for cancellation
-    let cancel_flag = Arc::new(AtomicBool::new(false));
-    
-    println!("\nAutomatic device detection started.");
-    println!("Press any key on the desired input device...");
-    println!("(Press Ctrl+C to cancel)");
-
-    // Communication channel for device detection
-    let (tx, rx) = mpsc::channel();
-    
-    // whether a Ctrl+C handler has already been registered
-    static CTRLC_HANDLER_INITIALIZED: AtomicBool = AtomicBool::new(false);
+    // Waits for a key press on any of the devices and returns the device it came from,
+    // the most active one if several devices fired. None after 30 seconds without input.
+    pub fn auto_detect_input_device(&self, device_list: &[DeviceInfo]) -> io::Result<Option<DeviceInfo>> {
+        let cancel = CancelToken::new()?;
+        let candidates = self.detect_input_devices(device_list, &cancel, DETECTION_TIMEOUT)?;
+        Ok(candidates.into_iter().next().map(|candidate| candidate.device))
+    }
     
-    // Register the Ctrl+C handler only if necessary
-    if !CTRLC_HANDLER_INITIALIZED.swap(true, Ordering::SeqCst) {
-        let cancel_flag_clone = cancel_flag.clone();
+    // Waits for key presses on the devices until the first one fires, then keeps listening for
+    // DETECTION_WINDOW to see whether other devices fire as well (e.g. keyboard and keypad pressed together).
+    // Returns every device that produced input, the most key presses first.
+    // Empty after `timeout` without input or when `cancel` is cancelled.
+    // Everything runs on the calling thread, nothing keeps reading the devices after it returns.
+    pub fn detect_input_devices(&self, device_list: &[DeviceInfo], cancel: &CancelToken, timeout: Duration) -> io::Result<Vec<DetectedDevice>> {
+        // Prefer keyboards, keypads and gamepads, so moving or clicking the mouse does not select it
+        let input_devices: Vec<DeviceInfo> = device_list.iter()
+            .filter(|d| matches!(d.kind(), DeviceKind::Keyboard | DeviceKind::Keypad | DeviceKind::Gamepad))
+            .cloned()
+            .collect();
         
-        // Wir versuchen den Handler zu setzen, ignorieren aber Fehler
-        let _ = ctrlc::set_handler(move || {
-            cancel_flag_clone.store(true, Ordering::SeqCst);
-        });
-    } else {
-        // If we are here, the handler is already set up
-        // We don't need to do anything
-    }
-
-    let mut handles = Vec::new();
-
-    // Start threads for each device to be checked
-    for device in &devices_to_check {
-        let device_clone = device.clone();
-        let tx_clone = tx.clone();
-        let cancel_flag_clone = cancel_flag.clone();
-
-        let handle = thread::spawn(move || {
-            let path = &device_clone.device_path;
-
-            // Try to open the device
-            let file = match File::open(path) {
-                Ok(f) => f,
-                Err(_) => return Ok(()),
-            };
-
-            // Set Non-Blocking mode
-            let fd = file.as_raw_fd();
-            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL, 0) };
-            if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
-                return Err(io::Error::last_os_error());
+        let devices_to_check = if input_devices.is_empty() {
+            device_list.to_vec()
+        } else {
+            input_devices
+        };
+        
+        println!("\nAutomatic device detection started.");
+        println!("Press any key on the desired input device...");
+        
+        // Open every device to be checked, unavailable devices are skipped
+        let mut readers: Vec<(DeviceInfo, EventReader)> = devices_to_check.iter()
+            .filter_map(|device| {
+                let file = File::open(&device.device_path).ok()?;
+                let reader = EventReader::new(file).ok()?;
+                Some((device.clone(), reader))
+            })
+            .collect();
+        
+        // Presses within the first 200ms are ignored (e.g. the release of the key that started the detection)
+        let debounce_time = Duration::from_millis(200);
+        let start = Instant::now();
+        
+        // Device path -> candidate, in the order the devices fired first
+        let mut candidates: Vec<DetectedDevice> = Vec::new();
+        let mut deadline = start + timeout;
+        
+        // Wait for input on any device, the cancellation or the deadline
+        loop {
+            if cancel.is_cancelled() {
+                println!("Detection canceled.");
+                return Ok(Vec::new());
             }
-
-            // Set up Event Reader
-            let mut reader = BufReader::new(file);
-            let mut buffer = [0u8; std::mem::size_of::<InputEvent>()];
             
-            // Debounce setting for key presses
-            let debounce_time = Duration::from_millis(200);
-            let mut last_event_time = std::time::Instant::now();
-
-            // Main loop for detecting keyboard input
-            loop {
-                // Check if cancellation was requested
-                if cancel_flag_clone.load(Ordering::SeqCst) {
-                    return Ok(());
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            
+            let mut fds: Vec<libc::pollfd> = readers.iter()
+                .map(|(_, reader)| poller::pollfd(reader.fd()))
+                .collect();
+            fds.push(poller::pollfd(cancel.wake.fd()));
+            
+            if poller::poll_fds(&mut fds, Some(deadline - now))? == 0 {
+                continue;
+            }
+            
+            for (index, (device, reader)) in readers.iter_mut().enumerate() {
+                if !poller::is_readable(&fds[index]) {
+                    continue;
                 }
-
-                // Short pause to reduce CPU usage
-                thread::sleep(Duration::from_millis(15));
-
-                // Try to read an event
-                match reader.read_exact(&mut buffer) {
-                    Ok(_) => {
-                        let event = unsafe { std::ptr::read(buffer.as_ptr() as *const InputEvent) };
-
-                        // Only consider key presses
-                        if event.type_ == EV_KEY && event.value == KEY_PRESS {
-                            let now = std::time::Instant::now();
-                            // Check for debounce time
-                            if now.duration_since(last_event_time) >= debounce_time {
-                                // Found! Send the device over the channel
-                                let _ = tx_clone.send(device_clone.clone());
-                                return Ok(());
+                
+                while let Ok(Some(event)) = reader.read_event() {
+                    // Only consider key presses and wheel/knob steps
+                    if !is_trigger_press(&event) || start.elapsed() < debounce_time {
+                        continue;
+                    }
+                    
+                    match candidates.iter_mut().find(|c| c.device.device_path == device.device_path) {
+                        Some(candidate) => candidate.presses += 1,
+                        None => {
+                            // First input at all: only wait a little longer for other devices
+                            if candidates.is_empty() {
+                                deadline = Instant::now() + DETECTION_WINDOW;
                             }
-                            last_event_time = now;
-                        }
-                    },
-                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
-                    Err(e) => return Err(e),
+                            candidates.push(DetectedDevice { device: device.clone(), presses: 1 });
+                        },
+                    }
                 }
             }
-        });
-
-        handles.push(handle);
-    }
-
-    // Timeout for detection
-    let timeout = Duration::from_secs(30);
-    let start = std::time::Instant::now();
-    let mut detected_device = None;
-
-    // Warte auf Ergebnis oder Timeout
-    loop {
-        // Check for cancellation
-        if cancel_flag.load(Ordering::SeqCst) {
-            println!("Detection canceled.");
-            break;
-        }
-
-        // Check if a device was detected
-        if let Ok(device_info) = rx.try_recv() {
-            detected_device = Some(device_info);
-            break;
+            
+            // Devices that were removed during detection are not checked any further
+            let closed: Vec<usize> = (0..readers.len()).filter(|&i| poller::is_closed(&fds[i])).collect();
+            for index in closed.into_iter().rev() {
+                readers.remove(index);
+            }
         }
-
-        // Check for timeout
-        if start.elapsed() > timeout {
+        
+        if candidates.is_empty() {
             println!("Timeout: No input detected.");
-            break;
         }
-
-        // Short pause
-        thread::sleep(Duration::from_millis(50));
-    }
-
-    // Clean up
-    drop(handles);
-    thread::sleep(Duration::from_millis(500));
-    
-    // Empty the channel
-    while rx.try_recv().is_ok() {}
-
-    // Gib erkanntes Gerät zurück
-    Ok(detected_device)
-}
-
-    pub fn detect_input_from_device(&self, device: &DeviceInfo, tx: mpsc::Sender<DeviceInfo>) -> io::Result<()> {
-        // Try to open device
-        let file = match File::open(&device.device_path) {
-            Ok(f) => f,
-            Err(_) => return Ok(()), // Skip unavailable devices
-        };
-        
-        // Create event reader
-        let mut event_reader = EventReader::new(file)?;
-        let debounce_time = Duration::from_millis(200);
-        let last_event_time = std::time::Instant::now();
         
-        loop {
-            thread::sleep(Duration::from_millis(15));
-            
-            match event_reader.read_event() {
-                Ok(Some(event)) if event.type_ == EV_KEY && event.value == KEY_PRESS => {
-                    let now = std::time::Instant::now();
-                    if now.duration_since(last_event_time) >= debounce_time {
-                        let _ = tx.send(device.clone());
-                        return Ok(());
-                    }
-                },
-                Ok(_) => {}, // Skip other events
-                Err(e) if e.kind() != io::ErrorKind::WouldBlock => return Err(e),
-                _ => {}
-            }
-        }
+        // Most active device first, the one that fired first wins a tie (stable sort)
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.presses));
+        Ok(candidates)
     }
     
     // Device blocking and monitoring
-    pub fn block_input_device(&self, device: &DeviceInfo) -> io::Result<()> {
+    // Opens and grabs a device and monitors it on its own thread. The thread sleeps in poll()
+    // until the device has input or the monitor is stopped. Several devices can be blocked at the same time,
+    // each one stays blocked until its handle is stopped or dropped, or the device is removed.
+    pub fn start_monitor(&'static self, device: &DeviceInfo) -> io::Result<Monitor> {
         println!("\nInputs from {} are being blocked and only displayed in the console.", device.device_name);
-        println!("Press Ctrl+C to exit.\n");
         
-        // Open device
-        let file = File::open(&device.device_path)?;
-        let file_fd = file.as_raw_fd();
+        // Early out before opening the device, start_source checks again under the lock
+        if self.is_monitoring(&device.device_path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Device {} is already being blocked", device.device_path)
+            ));
+        }
         
-        // Grab device (requires root)
-        let eviocgrab = 1074021776; // EVIOCGRAB constant
-        let mut grab_success = true;
+        // Open device
+        let mut source = EvdevSource::open(&device.device_path)?;
         
-        // Wait for pending events to process
+        // Wait for pending events to process (e.g. the release of the key used for detection)
         thread::sleep(Duration::from_millis(300));
         
         // Clear pending events
-        let mut event_reader = EventReader::new(file)?;
-        while let Ok(Some(_)) = event_reader.read_event() {}
+        source.drain();
         
-        // Try to grab device
-        unsafe {
-            if libc::ioctl(file_fd, eviocgrab, 1) < 0 {
-                eprintln!("Warning: Could not completely block device input (EVIOCGRAB failed).");
-                eprintln!("         This only works with root privileges.");
-                grab_success = false;
-            } else {
-                // Add file descriptor to grabbed list
-                if let Ok(mut fds) = GRABBED_FDS.lock() {
-                    fds.push(file_fd);
-                }
+        self.start_source(device, Box::new(source))
+    }
+    
+    // Monitors any input source on its own thread: grabs it if possible and reports its events
+    // through on_key until the monitor is stopped or the source ends.
+    // Also used with a ReplaySource to feed recorded events without a real device.
+    pub fn start_source(&'static self, device: &DeviceInfo, mut source: Box<dyn InputSource>) -> io::Result<Monitor> {
+        static NEXT_MONITOR_ID: AtomicU64 = AtomicU64::new(1);
+        
+        let id = NEXT_MONITOR_ID.fetch_add(1, Ordering::SeqCst);
+        let running = Arc::new(AtomicBool::new(true));
+        let wake = Arc::new(WakeFd::new()?);
+        
+        // Check and register under one lock, two callers (e.g. hotplug and loading the saved devices)
+        // must not both start a monitor for the same device
+        let grabbed = {
+            let mut monitors = self.monitors.lock()
+                .map_err(|_| io::Error::other("Monitor list is poisoned"))?;
+            if monitors.contains_key(&device.device_path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Device {} is already being blocked", device.device_path)
+                ));
             }
-        }
+            
+            // Try to grab device (requires root)
+            let grabbed_fd = source.grab();
+            let led_fd = source.fd().filter(|&fd| DeviceCapabilities::read(fd).is_ok_and(|c| c.leds));
+            
+            monitors.insert(device.device_path.clone(), MonitorState {
+                id,
+                device: device.clone(),
+                running: Arc::clone(&running),
+                wake: Arc::clone(&wake),
+                grabbed_fd,
+                led_fd,
+            });
+            grabbed_fd.is_some()
+        };
         
-        // Set monitoring status
-        MONITOR_RUNNING.store(true, Ordering::SeqCst);
+        let thread_device = device.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("monitor {}", device.device_path))
+            .spawn(move || {
+                let result = self.run_monitor(&thread_device, source.as_mut(), grabbed, &running, &wake);
+                
+                // Release device when done (unless the handle already did)
+                self.release_monitor(&thread_device.device_path, id);
+                self.on_monitor_end.trigger(&thread_device);
+                result
+            });
         
-        // Create channel for event communication
-        let (tx, rx) = mpsc::channel();
-        let on_key_down = Arc::clone(&self.on_key);
+        match spawned {
+            Ok(thread) => Ok(Monitor {
+                instance: self,
+                device: device.clone(),
+                id,
+                thread: Some(thread),
+            }),
+            Err(e) => {
+                self.release_monitor(&device.device_path, id);
+                Err(e)
+            },
+        }
+    }
+    
+    // Monitors a source on the calling thread until it ends, e.g. to replay a recording in a test
+    pub fn monitor_source(&'static self, device: &DeviceInfo, source: Box<dyn InputSource>) -> io::Result<()> {
+        self.start_source(device, source)?.wait()
+    }
+    
+    // Event loop of a monitor thread
+    fn run_monitor(&self, device: &DeviceInfo, source: &mut dyn InputSource, grabbed: bool, running: &AtomicBool, wake: &WakeFd) -> io::Result<()> {
+        let device_id = device.device_id();
         
-        // Shared map for key status
-        let blocked_keys = Arc::new(Mutex::new(HashMap::<u16, bool>::with_capacity(128)));
-        let blocked_keys_clone = Arc::clone(&blocked_keys);
+        // Key status (down/up) of this device
+        let mut blocked_keys = HashMap::<u16, bool>::with_capacity(128);
         
-        // Start thread to read input (without self-reference)
-        let event_reader_mtx = Arc::new(Mutex::new(event_reader));
-        let reader_clone = Arc::clone(&event_reader_mtx);
+        // Sticks, hats and analog triggers of gamepads
+        let mut axis_triggers = source.axis_triggers();
         
-        thread::spawn(move || {
-            loop {
-                if !MONITOR_RUNNING.load(Ordering::SeqCst) {
-                    break;
-                }
-                
-                thread::sleep(Duration::from_millis(10));
-                
-                let event_result = {
-                    let mut reader = reader_clone.lock().unwrap();
-                    reader.read_event()
-                };
-                
-                match event_result {
-                    Ok(Some(event)) if event.type_ == EV_KEY => {
-                        // Update key status
-                        if let Ok(mut keys) = blocked_keys_clone.lock() {
-                            keys.insert(event.code, event.value != 0);
-                        }
-                        
-                        // Send event to main thread
-                        let _ = tx.send((event.code, event.value));
-                    },
-                    Ok(_) => {}, // Skip other events
-                    Err(e) if e.kind() != io::ErrorKind::WouldBlock => {
-                        eprintln!("Error reading from device: {}", e);
-                        break;
-                    },
-                    _ => {}
-                }
-            }
-        });
+        // Selective grab: inputs that are no macro triggers are passed on through a virtual device.
+        // Created with the grab, so the first passed on key does not wait for the new device to be set up.
+        let mut forwarder = if grabbed { source.fd() } else { None }
+            .and_then(|fd| VirtualDevice::mirror(fd, &device.device_name)
+                .map_err(|e| eprintln!("Warning: Could not create virtual device, unbound keys stay blocked with selective grab: {}", e))
+                .ok());
+        let mut handled_keys = HashMap::<u16, bool>::new(); // Decision made when the key went down
+        let mut frame = ForwardFrame::default();            // Events of the current frame to pass on, sent with its SYN_REPORT
         
         println!("Blocking active! Keyboard inputs are now being intercepted and displayed in the console.\n");
         
-        // Read events from channel
         loop {
-            if !MONITOR_RUNNING.load(Ordering::SeqCst) {
-                break;
+            if !running.load(Ordering::SeqCst) {
+                break Ok(());
             }
             
-            match rx.recv_timeout(Duration::from_millis(100)) {
-                Ok((key_code, state)) => {
-                    let key_state = match state {
-                        0 => KeyState::Up,
-                        1 => KeyState::Down,
-                        2 => KeyState::Press,
+            let event = match source.next_event(wake) {
+                Ok(SourceEvent::Event(event)) => event,
+                Ok(SourceEvent::Woken) => continue, // Stop requested, checked above
+                Ok(SourceEvent::Closed) => {
+                    eprintln!("Device {} was removed or its recording ended", device.device_path);
+                    break Ok(());
+                },
+                Err(e) => {
+                    eprintln!("Error reading from device: {}", e);
+                    break Ok(());
+                },
+            };
+            
+            self.record_event(&device.device_path, &event);
+            
+            let filter = self.trigger_filter.read().ok().and_then(|filter| filter.clone());
+            let is_trigger = |info: &KeyInfo| filter.as_ref().is_none_or(|filter| filter(info));
+            
+            let mut forward = false;
+            match event.type_ {
+                EV_KEY => {
+                    // Update key status
+                    blocked_keys.insert(event.code, event.value != 0);
+                    
+                    let key_state = match event.value {
+                        KEY_RELEASE => KeyState::Up,
+                        KEY_PRESS => KeyState::Down,
+                        KEY_REPEAT => KeyState::Press,
                         _ => KeyState::Press,
                     };
-            
+                    
                     let key_info = KeyInfo {
-                        name: key_to_string(key_code), 
-                        state: key_state
+                        name: key_to_string(event.code), 
+                        state: key_state,
+                        device: device.device_name.clone(),
+                        device_id: device_id.clone(),
+                        delta: 0,
                     };
                     
-                    on_key_down.trigger(&key_info);
+                    // Repeat and release follow the decision of the press
+                    let handled = match event.value {
+                        KEY_PRESS => {
+                            let handled = is_trigger(&key_info);
+                            handled_keys.insert(event.code, handled);
+                            handled
+                        },
+                        _ => handled_keys.get(&event.code).copied().unwrap_or_else(|| is_trigger(&key_info)),
+                    };
+                    if event.value == KEY_RELEASE {
+                        handled_keys.remove(&event.code);
+                    }
+                    
+                    if handled {
+                        self.on_key.trigger(&key_info);
+                    } else {
+                        forward = true;
+                    }
+                },
+                EV_ABS => {
+                    // Axis pushed past / back below its threshold
+                    let axis_info = |name: &str, state| KeyInfo {
+                        name: name.to_string(),
+                        state,
+                        device: device.device_name.clone(),
+                        device_id: device_id.clone(),
+                        delta: 0,
+                    };
+                    for (name, pressed) in axis_triggers.update(event.code, event.value) {
+                        let key_info = axis_info(name, if pressed { KeyState::Down } else { KeyState::Up });
+                        if is_trigger(&key_info) {
+                            self.on_key.trigger(&key_info);
+                        }
+                    }
+                    // An axis can't be split, if one of its directions is bound it is held back completely
+                    forward = [true, false].iter()
+                        .filter_map(|&positive| key_codes::abs_to_string(event.code, positive))
+                        .all(|name| !is_trigger(&axis_info(name, KeyState::Down)));
+                },
+                EV_REL => {
+                    // A wheel or knob step is reported like a tap: down, then up
+                    let trigger = key_codes::rel_to_string(event.code, event.value)
+                        .filter(|name| is_trigger(&KeyInfo {
+                            name: name.to_string(),
+                            state: KeyState::Down,
+                            device: device.device_name.clone(),
+                            device_id: device_id.clone(),
+                            delta: event.value,
+                        }));
+                    
+                    match trigger {
+                        Some(name) => {
+                            for key_state in [KeyState::Down, KeyState::Up] {
+                                self.on_key.trigger(&KeyInfo {
+                                    name: name.to_string(),
+                                    state: key_state,
+                                    device: device.device_name.clone(),
+                                    device_id: device_id.clone(),
+                                    delta: event.value,
+                                });
+                            }
+                        },
+                        None => forward = true, // Mouse movement, unbound wheels
+                    }
                 },
-                Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                EV_MSC => forward = true, // Scan codes go with their key, see ForwardFrame
+                EV_SYN => {
+                    // End of the frame: pass on what is left of it, frames of triggers are dropped
+                    let events = frame.finish(event);
+                    if let Some(virtual_device) = forwarder.as_mut().filter(|_| !events.is_empty()) {
+                        let sent = events.iter()
+                            .try_for_each(|queued| virtual_device.emit(queued.type_, queued.code, queued.value));
+                        if let Err(e) = sent {
+                            eprintln!("Error forwarding event: {}", e);
+                        }
+                    }
+                    continue;
+                },
+                _ => {}, // Skip other events
             }
-        }
-        
-        // Release device when done
-        if grab_success {
-            unsafe {
-                let _ = libc::ioctl(file_fd, eviocgrab, 0);
-                
-                if let Ok(mut fds) = GRABBED_FDS.lock() {
-                    fds.retain(|&fd| fd != file_fd);
-                }
+            
+            if filter.is_some() && forwarder.is_some() {
+                frame.push(event, forward);
             }
         }
+    }
+    
+    // Selective grab: with a filter only inputs it accepts trigger macros, everything else
+    // is passed on to the system through a virtual device. None blocks the whole device.
+    pub fn set_trigger_filter(&self, filter: Option<TriggerFilter>) {
+        if let Ok(mut current) = self.trigger_filter.write() {
+            *current = filter;
+        }
+    }
+    
+    // Switches an LED on every blocked device that has LEDs, or only on the devices with the given name.
+    // Returns the number of devices the LED was switched on.
+    pub fn set_led(&self, led: u16, on: bool, device_name: Option<&str>) -> usize {
+        let Ok(monitors) = self.monitors.lock() else { return 0 };
         
-        Ok(())
+        monitors.values()
+            .filter(|state| device_name.is_none_or(|name| state.device.device_name == name))
+            .filter_map(|state| state.led_fd)
+            .filter(|&fd| match evdev::write_led(fd, led, on) {
+                Ok(_) => true,
+                Err(e) => {
+                    eprintln!("Error switching LED {} (fd {}): {}", led, fd, e);
+                    false
+                },
+            })
+            .count()
     }
     
-    pub fn stop_monitor_now(&self) {
-        println!("Stopping monitoring and releasing all blocked devices...");
+    // Writes the event to the recording of the device, if one is running
+    fn record_event(&self, device_path: &str, event: &InputEvent) {
+        let Ok(mut recorders) = self.recorders.lock() else { return };
+        let Some(recorder) = recorders.get_mut(device_path) else { return };
         
-        // Signal monitoring to stop
-        MONITOR_RUNNING.store(false, Ordering::SeqCst);
+        if let Err(e) = recorder.write(event) {
+            eprintln!("Error writing recording {}: {}", recorder.path().display(), e);
+            recorders.remove(device_path);
+        }
+    }
+    
+    // Starts recording the raw events of a blocked device to a file (format: see input_source.rs)
+    pub fn start_recording(&self, device_path: &str, file: &Path) -> io::Result<()> {
+        let device_name = self.monitors.lock()
+            .ok()
+            .and_then(|monitors| monitors.get(device_path).map(|state| state.device.device_name.clone()))
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Device {} is not being blocked", device_path)
+            ))?;
         
-        // Release all grabbed devices
-        let eviocgrab = 1074021776; // EVIOCGRAB constant
+        let recorder = Recorder::create(file, &device_name)?;
+        println!("Recording events of {} to {}", device_name, file.display());
+        if let Ok(mut recorders) = self.recorders.lock() {
+            recorders.insert(device_path.to_string(), recorder);
+        }
+        Ok(())
+    }
+    
+    // Stops the recording of a device, returns the file it was written to
+    pub fn stop_recording(&self, device_path: &str) -> Option<PathBuf> {
+        let recorder = self.recorders.lock().ok()?.remove(device_path)?;
+        println!("Recording of {} stopped", device_path);
+        Some(recorder.path().to_path_buf())
+    }
+    
+    // Whether a key binding saved for the device id `saved_id` belongs to the blocked device with `device_id`,
+    // see DeviceInfo::is_bound_to. Ids that are not blocked (e.g. in tests) only match themselves.
+    pub fn is_bound_device(&self, saved_id: &str, device_id: &str) -> bool {
+        if saved_id == device_id {
+            return true;
+        }
+        let Ok(monitors) = self.monitors.lock() else { return false };
+        let connected: Vec<String> = monitors.values().map(|state| state.device.device_id()).collect();
+        monitors.values()
+            .find(|state| state.device.device_id() == device_id)
+            .is_some_and(|state| state.device.is_bound_to(saved_id, &connected))
+    }
+    
+    // Path and name of every blocked device
+    pub fn monitored_devices(&self) -> Vec<(String, String)> {
+        self.monitors.lock()
+            .map(|monitors| monitors.iter()
+                .map(|(path, state)| (path.clone(), state.device.device_name.clone()))
+                .collect())
+            .unwrap_or_default()
+    }
+    
+    // Whether a monitor is running for the given device path
+    pub fn is_monitoring(&self, device_path: &str) -> bool {
+        self.monitors.lock()
+            .map(|monitors| monitors.contains_key(device_path))
+            .unwrap_or(false)
+    }
+    
+    // Removes the monitor with this id, signals its thread to stop and ungrabs the device.
+    // A newer monitor of the same device path is left alone.
+    fn release_monitor(&self, device_path: &str, id: u64) -> bool {
+        let state = match self.monitors.lock() {
+            Ok(mut monitors) if monitors.get(device_path).is_some_and(|state| state.id == id) => monitors.remove(device_path),
+            _ => None,
+        };
         
-        if let Ok(mut fds) = GRABBED_FDS.lock() {
-            for &fd in fds.iter() {
-                unsafe {
-                    let result = libc::ioctl(fd, eviocgrab, 0);
-                    if result >= 0 {
-                        println!("Device with fd {} released", fd);
-                    } else {
-                        eprintln!("Error releasing device with fd {}: {}", 
-                                  fd, io::Error::last_os_error());
-                    }
+        match state {
+            Some(state) => {
+                state.running.store(false, Ordering::SeqCst);
+                state.wake.wake();
+                if let Some(fd) = state.grabbed_fd {
+                    Self::ungrab(fd);
                 }
+                self.stop_recording(device_path);
+                true
+            },
+            None => false,
+        }
+    }
+    
+    fn ungrab(fd: i32) {
+        unsafe {
+            let result = libc::ioctl(fd, EVIOCGRAB as _, 0);
+            if result >= 0 {
+                println!("Device with fd {} released", fd);
+            } else {
+                eprintln!("Error releasing device with fd {}: {}", 
+                          fd, io::Error::last_os_error());
             }
-            
-            fds.clear();
         }
-        
-        thread::sleep(Duration::from_millis(300));
     }
     
     // File operations
@@ -724,12 +1221,98 @@ pub fn auto_detect_input_device(&self, device_list: &[DeviceInfo]) -> io::Result
         println!("\nSaved input devices:");
         
         for (i, device) in devices.iter().enumerate() {
-            println!("[{}] {} - {} (USB Port: {})",
+            println!("[{}] {} - {} ({}, USB Port: {})",
                 i + 1,
                 device.device_path,
                 device.device_name,
+                device.kind(),
                 device.usb_port
             );
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn input(type_: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent { tv_sec: 0, tv_usec: 0, type_, code, value }
+    }
+    
+    #[test]
+    fn frame_drops_the_scan_code_of_a_bound_key() {
+        let mut frame = ForwardFrame::default();
+        frame.push(input(EV_MSC, MSC_SCAN, 0x70004), true);
+        frame.push(input(EV_KEY, 30, 1), false); // A, bound
+        frame.push(input(EV_MSC, MSC_SCAN, 0x70005), true);
+        frame.push(input(EV_KEY, 48, 1), true);  // B, unbound
+        frame.push(input(EV_REL, 0, 3), true);   // Mouse movement
+        
+        assert_eq!(frame.finish(input(EV_SYN, 0, 0)), [
+            input(EV_MSC, MSC_SCAN, 0x70005),
+            input(EV_KEY, 48, 1),
+            input(EV_REL, 0, 3),
+            input(EV_SYN, 0, 0),
+        ]);
+    }
+    
+    #[test]
+    fn frame_of_bound_events_is_not_passed_on() {
+        let mut frame = ForwardFrame::default();
+        frame.push(input(EV_MSC, MSC_SCAN, 0x70004), true);
+        frame.push(input(EV_KEY, 30, 1), false);
+        frame.push(input(EV_ABS, 0x02, 255), false);
+        frame.push(input(EV_MSC, 0x05, 1000), true); // MSC_TIMESTAMP
+        assert!(frame.finish(input(EV_SYN, 0, 0)).is_empty());
+        
+        // The next frame starts empty
+        frame.push(input(EV_KEY, 48, 0), true);
+        assert_eq!(frame.finish(input(EV_SYN, 0, 0)), [input(EV_KEY, 48, 0), input(EV_SYN, 0, 0)]);
+    }
+    
+    fn keyboard(phys: &str, uniq: &str) -> DeviceInfo {
+        DeviceInfo {
+            device_name: "USB Keyboard".to_string(),
+            bus_type: 0x0003,
+            vendor_id: 0x046d,
+            product_id: 0xc31c,
+            phys: phys.to_string(),
+            uniq: uniq.to_string(),
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn binding_follows_the_device_to_another_port() {
+        let saved = keyboard("usb-0000:00:14.0-2/input0", "").device_id();
+        let replugged = keyboard("usb-0000:00:14.0-4.1/input0", "");
+        
+        assert!(replugged.is_bound_to(&saved, &[replugged.device_id()]));
+    }
+    
+    #[test]
+    fn identical_devices_keep_their_port() {
+        let first = keyboard("usb-0000:00:14.0-2/input0", "");
+        let second = keyboard("usb-0000:00:14.0-3/input0", "");
+        let connected = [first.device_id(), second.device_id()];
+        
+        assert!(first.is_bound_to(&first.device_id(), &connected));
+        assert!(!second.is_bound_to(&first.device_id(), &connected));
+    }
+    
+    #[test]
+    fn serial_numbers_have_to_match() {
+        let saved = keyboard("usb-0000:00:14.0-2/input0", "A1").device_id();
+        
+        assert!(keyboard("usb-0000:00:14.0-4/input0", "A1").is_bound_to(&saved, &[]));
+        assert!(!keyboard("usb-0000:00:14.0-2/input0", "B2").is_bound_to(&saved, &[]));
+    }
+    
+    #[test]
+    fn other_models_do_not_match() {
+        let saved = keyboard("usb-0000:00:14.0-2/input0", "").device_id();
+        let other = DeviceInfo { product_id: 0xc31d, ..keyboard("usb-0000:00:14.0-2/input0", "") };
+        
+        assert!(!other.is_bound_to(&saved, &[]));
+    }
+}
@@ -1,9 +1,12 @@
-use x11rb::protocol::xtest::ConnectionExt as XTestConnectionExt;
 use std::fmt;
 use std::error::Error;
-use x11rb::connection::Connection;
+use std::io;
+use std::sync::Arc;
 use std::{thread, time::Duration};
 
+use crate::keymap::{self, Keymap, ISO_LEVEL3_SHIFT, NO_SYMBOL, SHIFT_L};
+use crate::output::{MouseButton, OutputBackend, OutputSink};
+
 
 // X11 Event Types
 const KEY_PRESS: u8 = 2;
@@ -14,6 +17,7 @@ const KEY_RELEASE: u8 = 3;
 pub enum KeySimError {
     X11ReplyError(x11rb::errors::ReplyError),
     X11ConnectionError(x11rb::errors::ConnectionError),
+    UinputError(io::Error),
     LuaError(mlua::Error),
     OtherError(String),
 }
@@ -23,6 +27,7 @@ impl fmt::Display for KeySimError {
         match self {
             KeySimError::X11ReplyError(e) => write!(f, "X11 reply error: {}", e),
             KeySimError::X11ConnectionError(e) => write!(f, "X11 connection error: {}", e),
+            KeySimError::UinputError(e) => write!(f, "uinput error: {}", e),
             KeySimError::LuaError(e) => write!(f, "Lua error: {}", e),
             KeySimError::OtherError(s) => write!(f, "Error: {}", s),
         }
@@ -43,6 +48,12 @@ impl From<x11rb::errors::ConnectionError> for KeySimError {
     }
 }
 
+impl From<io::Error> for KeySimError {
+    fn from(error: io::Error) -> Self {
+        KeySimError::UinputError(error)
+    }
+}
+
 impl From<mlua::Error> for KeySimError {
     fn from(error: mlua::Error) -> Self {
         KeySimError::LuaError(error)
@@ -61,25 +72,40 @@ impl From<&str> for KeySimError {
     }
 }
 
-// Main structure for X11 connection and key inputs
+// How type_text types a character: the modifiers to hold, the key and
+// the keysym to bind to the key first if the character is not on the layout
+struct CharStroke {
+    modifiers: Vec<u8>,
+    keycode: u8,
+    remap: Option<u32>,
+}
+
+// Main structure for key and mouse inputs, sent to an OutputSink (XTest, uinput or a recording).
+// Keycodes are X11 keycodes for every sink.
 pub struct KeyboardTrigger {
-    conn: x11rb::rust_connection::RustConnection,
+    output: Arc<dyn OutputSink>,
 }
 
 impl KeyboardTrigger {
+    // Uses the backend chosen with OutputBackend::set_default
     pub fn new() -> Result<Self, KeySimError> {
-        let (conn, _) = x11rb::connect(None)
-            .map_err(|e| KeySimError::OtherError(format!("Failed to connect to X11 server: {}", e)))?;
-        Ok(KeyboardTrigger { conn })
+        Self::with_backend(OutputBackend::default_backend())
+    }
+
+    pub fn with_backend(backend: OutputBackend) -> Result<Self, KeySimError> {
+        Ok(Self::with_output(backend.open()?))
+    }
+
+    pub fn with_output(output: Arc<dyn OutputSink>) -> Self {
+        KeyboardTrigger { output }
     }
 
     // Optimized method: Batch sending for multiple events
     fn send_key_events(&self, events: &[(u8, u8)]) -> Result<(), KeySimError> {
         for &(event_type, keycode) in events {
-            self.conn.xtest_fake_input(event_type, keycode, 0, 0, 0, 0, 0)?;
+            self.output.key(keycode, event_type == KEY_PRESS)?;
         }
-        self.conn.flush()?;
-        Ok(())
+        self.output.flush()
     }
 
     // Presses a key
@@ -148,10 +174,105 @@ impl KeyboardTrigger {
         Ok(())
     }
 
+    // Types text with the keyboard layout of the output, delay_ms after every character.
+    // Characters that are not on the layout are bound to a spare keycode for the time they are typed,
+    // outputs that can't do that (uinput) only type the characters of the layout.
+    // All characters are checked first, text that can't be typed completely is not typed at all.
+    pub fn type_text(&self, text: &str, delay_ms: Option<u64>) -> Result<(), KeySimError> {
+        let delay = delay_ms.unwrap_or(10);
+        let keymap = self.output.keymap()
+            .ok_or("Keyboard layout is unknown, type_text needs an X server (or XWayland)")?;
+        let strokes = text.chars()
+            .map(|c| self.char_stroke(&keymap, c))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut remapped = None;
+
+        let result = strokes.iter().try_for_each(|stroke| {
+            // Bound once for a run of the same character
+            if let Some(keysym) = stroke.remap.filter(|&keysym| remapped != Some(keysym)) {
+                self.output.remap(stroke.keycode, keysym)?;
+                remapped = Some(keysym);
+            }
+
+            self.type_stroke(stroke)?;
+
+            if delay > 0 {
+                thread::sleep(Duration::from_millis(delay));
+            }
+            Ok::<(), KeySimError>(())
+        });
+
+        // Give the spare keycode back, also after an error
+        if let (Some(spare), Some(_)) = (keymap.spare_keycode(), remapped) {
+            self.output.remap(spare, NO_SYMBOL)?;
+        }
+        result
+    }
+
+    fn char_stroke(&self, keymap: &Keymap, c: char) -> Result<CharStroke, KeySimError> {
+        let keysym = keymap::char_to_keysym(c);
+        let Some((keycode, level)) = keymap.find(keysym) else {
+            if !self.output.can_remap() {
+                return Err(format!("'{}' is not on the keyboard layout, this output can only type characters of the layout", c).into());
+            }
+            let spare = keymap.spare_keycode()
+                .ok_or_else(|| format!("'{}' is not on the keyboard layout and there is no free keycode", c))?;
+            return Ok(CharStroke { modifiers: Vec::new(), keycode: spare, remap: Some(keysym) });
+        };
+
+        let mut modifiers = Vec::new();
+        if level.shift {
+            modifiers.push(keymap.modifier(SHIFT_L).ok_or_else(|| format!("No shift key to type '{}'", c))?);
+        }
+        if level.altgr {
+            modifiers.push(keymap.modifier(ISO_LEVEL3_SHIFT).ok_or_else(|| format!("No AltGr key to type '{}'", c))?);
+        }
+        Ok(CharStroke { modifiers, keycode, remap: None })
+    }
+
+    // Taps the key of a character with its modifiers held. Modifiers that are down are released after an error too.
+    fn type_stroke(&self, stroke: &CharStroke) -> Result<(), KeySimError> {
+        let mut pressed = 0;
+        let result = stroke.modifiers.iter()
+            .try_for_each(|&modifier| {
+                self.output.key(modifier, true)?;
+                pressed += 1;
+                Ok::<(), KeySimError>(())
+            })
+            .and_then(|()| {
+                self.output.key(stroke.keycode, true)?;
+                self.output.key(stroke.keycode, false)
+            });
+
+        let released = stroke.modifiers[..pressed].iter().rev()
+            .try_for_each(|&modifier| self.output.key(modifier, false));
+        let flushed = self.output.flush();
+        result.and(released).and(flushed)
+    }
+
+    // Clicks a mouse button, held for delay_ms
+    pub fn click(&self, button: MouseButton, delay_ms: Option<u64>) -> Result<(), KeySimError> {
+        self.output.button(button, true)?;
+        let delay = delay_ms.unwrap_or(20);
+        if delay > 0 {
+            thread::sleep(Duration::from_millis(delay));
+        }
+        self.output.button(button, false)
+    }
+
+    // Moves the mouse pointer relative to its position
+    pub fn mouse_move(&self, dx: i32, dy: i32) -> Result<(), KeySimError> {
+        self.output.move_pointer(dx, dy)
+    }
+
+    // Scroll wheel steps, positive = up
+    pub fn scroll(&self, steps: i32) -> Result<(), KeySimError> {
+        self.output.scroll(steps)
+    }
+
     // Flushes the buffer
     pub fn flush(&self) -> Result<(), KeySimError> {
-        self.conn.flush()?;
-        Ok(())
+        self.output.flush()
     }
 }
 
@@ -159,6 +280,127 @@ impl KeyboardTrigger {
 impl Drop for KeyboardTrigger {
     fn drop(&mut self) {
         // Ensure all pending events are sent
-        let _ = self.conn.flush();
+        let _ = self.output.flush();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::RecordingSink;
+
+    // keycode 9 a/A, 10 e/E with € on AltGr, 11 Shift_L, 12 AltGr, 13 free
+    fn test_keymap() -> Keymap {
+        let mut keysyms = vec![NO_SYMBOL; 6 * 6];
+        keysyms[6..12].copy_from_slice(&[0x61, 0x41, 0, 0, 0, 0]);
+        keysyms[12..18].copy_from_slice(&[0x65, 0x45, 0, 0, 0x20ac, 0]);
+        keysyms[18] = SHIFT_L;
+        keysyms[24] = ISO_LEVEL3_SHIFT;
+        Keymap::new(8, 6, keysyms)
+    }
+
+    // Trace without the times, e.g. "key 9 down"
+    fn actions(sink: &RecordingSink) -> Vec<String> {
+        sink.trace().lines()
+            .map(|line| line.split_once(" ms ").map_or(line, |(_, action)| action).to_string())
+            .collect()
+    }
+
+    // Records like RecordingSink, fails to press one keycode and can't remap if asked to (like uinput)
+    struct LimitedSink {
+        recording: RecordingSink,
+        failing_keycode: Option<u8>,
+        can_remap: bool,
+    }
+
+    impl OutputSink for LimitedSink {
+        fn key(&self, keycode: u8, pressed: bool) -> Result<(), KeySimError> {
+            if pressed && Some(keycode) == self.failing_keycode {
+                return Err("Key could not be sent".into());
+            }
+            self.recording.key(keycode, pressed)
+        }
+
+        fn button(&self, button: MouseButton, pressed: bool) -> Result<(), KeySimError> {
+            self.recording.button(button, pressed)
+        }
+
+        fn move_pointer(&self, dx: i32, dy: i32) -> Result<(), KeySimError> {
+            self.recording.move_pointer(dx, dy)
+        }
+
+        fn scroll(&self, steps: i32) -> Result<(), KeySimError> {
+            self.recording.scroll(steps)
+        }
+
+        fn keymap(&self) -> Option<Keymap> {
+            self.recording.keymap()
+        }
+
+        fn remap(&self, keycode: u8, keysym: u32) -> Result<(), KeySimError> {
+            self.recording.remap(keycode, keysym)
+        }
+
+        fn can_remap(&self) -> bool {
+            self.can_remap
+        }
+    }
+
+    fn limited_sink(failing_keycode: Option<u8>, can_remap: bool) -> Arc<LimitedSink> {
+        Arc::new(LimitedSink { recording: RecordingSink::new().with_keymap(test_keymap()), failing_keycode, can_remap })
+    }
+
+    #[test]
+    fn type_text_holds_shift_and_altgr() {
+        let sink = Arc::new(RecordingSink::new().with_keymap(test_keymap()));
+        KeyboardTrigger::with_output(sink.clone()).type_text("aA€", Some(0)).unwrap();
+
+        assert_eq!(actions(&sink), [
+            "key 9 down", "key 9 up",
+            "key 11 down", "key 9 down", "key 9 up", "key 11 up",
+            "key 12 down", "key 10 down", "key 10 up", "key 12 up",
+        ]);
+    }
+
+    #[test]
+    fn type_text_binds_missing_characters_to_the_spare_keycode() {
+        let sink = Arc::new(RecordingSink::new().with_keymap(test_keymap()));
+        KeyboardTrigger::with_output(sink.clone()).type_text("ääa", Some(0)).unwrap();
+
+        // Bound once for both ä, given back at the end
+        assert_eq!(actions(&sink), [
+            "remap 13 to keysym 0xe4",
+            "key 13 down", "key 13 up",
+            "key 13 down", "key 13 up",
+            "key 9 down", "key 9 up",
+            "remap 13 back",
+        ]);
+    }
+
+    #[test]
+    fn type_text_gives_the_spare_keycode_back_after_an_error() {
+        let sink = limited_sink(Some(13), true);
+        let result = KeyboardTrigger::with_output(sink.clone()).type_text("aä", Some(0));
+
+        assert!(result.is_err());
+        assert_eq!(actions(&sink.recording), ["key 9 down", "key 9 up", "remap 13 to keysym 0xe4", "remap 13 back"]);
+    }
+
+    #[test]
+    fn type_text_releases_held_modifiers_after_an_error() {
+        let sink = limited_sink(Some(10), true);
+        let result = KeyboardTrigger::with_output(sink.clone()).type_text("€", Some(0));
+
+        assert!(result.is_err());
+        assert_eq!(actions(&sink.recording), ["key 12 down", "key 12 up"]);
+    }
+
+    #[test]
+    fn type_text_without_remap_fails_before_typing() {
+        let sink = limited_sink(None, false);
+        let result = KeyboardTrigger::with_output(sink.clone()).type_text("aä", Some(0));
+
+        assert!(result.is_err());
+        assert!(sink.recording.events().is_empty());
+    }
+}
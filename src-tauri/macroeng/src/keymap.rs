@@ -0,0 +1,391 @@
+// keymap.rs
+// Keyboard layout of the X server (GetKeyboardMapping): which keycode and modifiers type a character.
+// type_text uses it so text comes out right on every layout, e.g. "z" on a German QWERTZ keyboard.
+// The key names of press/release/tap/combo are resolved through it as well (KeyTable), it is rebuilt on MappingNotify.
+use std::collections::HashMap;
+use std::sync::{Arc, Once, RwLock};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as XProtoConnectionExt, Mapping};
+use x11rb::protocol::Event;
+
+use crate::key_codes;
+use crate::keyboard_trigger::KeySimError;
+
+pub const NO_SYMBOL: u32 = 0;
+pub const SHIFT_L: u32 = 0xffe1;
+pub const ISO_LEVEL3_SHIFT: u32 = 0xfe03;  // AltGr
+const RETURN: u32 = 0xff0d;
+const TAB: u32 = 0xff09;
+const BACKSPACE: u32 = 0xff08;
+const EURO_SIGN: u32 = 0x20ac;
+
+// Keysyms of characters outside Latin-1 are the Unicode code point plus this
+const UNICODE_KEYSYM_OFFSET: u32 = 0x0100_0000;
+
+// X11 keycodes of the evdev driver are the Linux key codes shifted by 8
+const X11_KEYCODE_OFFSET: u16 = 8;
+
+// Keysym names as in X11/keysymdef.h and XF86keysym.h (without the XK_ prefix).
+// Letters, digits and other characters are found by the character itself, e.g. "a" or "ö".
+const KEYSYM_NAMES: &[(&str, u32)] = &[
+    ("space", 0x20), ("exclam", 0x21), ("quotedbl", 0x22), ("numbersign", 0x23), ("dollar", 0x24),
+    ("percent", 0x25), ("ampersand", 0x26), ("apostrophe", 0x27), ("parenleft", 0x28), ("parenright", 0x29),
+    ("asterisk", 0x2a), ("plus", 0x2b), ("comma", 0x2c), ("minus", 0x2d), ("period", 0x2e), ("slash", 0x2f),
+    ("colon", 0x3a), ("semicolon", 0x3b), ("less", 0x3c), ("equal", 0x3d), ("greater", 0x3e), ("question", 0x3f),
+    ("at", 0x40), ("bracketleft", 0x5b), ("backslash", 0x5c), ("bracketright", 0x5d), ("asciicircum", 0x5e),
+    ("underscore", 0x5f), ("grave", 0x60), ("braceleft", 0x7b), ("bar", 0x7c), ("braceright", 0x7d), ("asciitilde", 0x7e),
+    ("section", 0xa7), ("degree", 0xb0), ("acute", 0xb4), ("mu", 0xb5), ("twosuperior", 0xb2), ("threesuperior", 0xb3),
+    ("adiaeresis", 0xe4), ("odiaeresis", 0xf6), ("udiaeresis", 0xfc), ("ssharp", 0xdf),
+    ("agrave", 0xe0), ("ccedilla", 0xe7), ("egrave", 0xe8), ("eacute", 0xe9), ("ugrave", 0xf9), ("EuroSign", EURO_SIGN),
+    ("BackSpace", BACKSPACE), ("Tab", TAB), ("Return", RETURN), ("Pause", 0xff13), ("Scroll_Lock", 0xff14),
+    ("Sys_Req", 0xff15), ("Escape", 0xff1b), ("Delete", 0xffff), ("Home", 0xff50), ("Left", 0xff51), ("Up", 0xff52),
+    ("Right", 0xff53), ("Down", 0xff54), ("Prior", 0xff55), ("Page_Up", 0xff55), ("Next", 0xff56), ("Page_Down", 0xff56),
+    ("End", 0xff57), ("Print", 0xff61), ("Insert", 0xff63), ("Menu", 0xff67), ("Mode_switch", 0xff7e), ("Num_Lock", 0xff7f),
+    ("KP_Enter", 0xff8d), ("KP_Home", 0xff95), ("KP_Left", 0xff96), ("KP_Up", 0xff97), ("KP_Right", 0xff98),
+    ("KP_Down", 0xff99), ("KP_Prior", 0xff9a), ("KP_Next", 0xff9b), ("KP_End", 0xff9c), ("KP_Begin", 0xff9d),
+    ("KP_Insert", 0xff9e), ("KP_Delete", 0xff9f), ("KP_Multiply", 0xffaa), ("KP_Add", 0xffab), ("KP_Separator", 0xffac),
+    ("KP_Subtract", 0xffad), ("KP_Decimal", 0xffae), ("KP_Divide", 0xffaf), ("KP_0", 0xffb0), ("KP_1", 0xffb1),
+    ("KP_2", 0xffb2), ("KP_3", 0xffb3), ("KP_4", 0xffb4), ("KP_5", 0xffb5), ("KP_6", 0xffb6), ("KP_7", 0xffb7),
+    ("KP_8", 0xffb8), ("KP_9", 0xffb9), ("KP_Equal", 0xffbd),
+    ("F1", 0xffbe), ("F2", 0xffbf), ("F3", 0xffc0), ("F4", 0xffc1), ("F5", 0xffc2), ("F6", 0xffc3), ("F7", 0xffc4),
+    ("F8", 0xffc5), ("F9", 0xffc6), ("F10", 0xffc7), ("F11", 0xffc8), ("F12", 0xffc9), ("F13", 0xffca), ("F14", 0xffcb),
+    ("F15", 0xffcc), ("F16", 0xffcd), ("F17", 0xffce), ("F18", 0xffcf), ("F19", 0xffd0), ("F20", 0xffd1), ("F21", 0xffd2),
+    ("F22", 0xffd3), ("F23", 0xffd4), ("F24", 0xffd5),
+    ("Shift_L", SHIFT_L), ("Shift_R", 0xffe2), ("Control_L", 0xffe3), ("Control_R", 0xffe4), ("Caps_Lock", 0xffe5),
+    ("Meta_L", 0xffe7), ("Meta_R", 0xffe8), ("Alt_L", 0xffe9), ("Alt_R", 0xffea), ("Super_L", 0xffeb), ("Super_R", 0xffec),
+    ("ISO_Level3_Shift", ISO_LEVEL3_SHIFT),
+    ("XF86MonBrightnessUp", 0x1008ff02), ("XF86MonBrightnessDown", 0x1008ff03), ("XF86KbdBrightnessUp", 0x1008ff05),
+    ("XF86KbdBrightnessDown", 0x1008ff06), ("XF86AudioLowerVolume", 0x1008ff11), ("XF86AudioMute", 0x1008ff12),
+    ("XF86AudioRaiseVolume", 0x1008ff13), ("XF86AudioPlay", 0x1008ff14), ("XF86AudioStop", 0x1008ff15),
+    ("XF86AudioPrev", 0x1008ff16), ("XF86AudioNext", 0x1008ff17), ("XF86HomePage", 0x1008ff18), ("XF86Mail", 0x1008ff19),
+    ("XF86Search", 0x1008ff1b), ("XF86AudioRecord", 0x1008ff1c), ("XF86Calculator", 0x1008ff1d), ("XF86Calendar", 0x1008ff20),
+    ("XF86Back", 0x1008ff26), ("XF86Forward", 0x1008ff27), ("XF86Stop", 0x1008ff28), ("XF86Refresh", 0x1008ff29),
+    ("XF86PowerOff", 0x1008ff2a), ("XF86WakeUp", 0x1008ff2b), ("XF86Eject", 0x1008ff2c), ("XF86ScreenSaver", 0x1008ff2d),
+    ("XF86WWW", 0x1008ff2e), ("XF86Sleep", 0x1008ff2f), ("XF86Favorites", 0x1008ff30), ("XF86AudioPause", 0x1008ff31),
+    ("XF86AudioMedia", 0x1008ff32), ("XF86MyComputer", 0x1008ff33), ("XF86AudioRewind", 0x1008ff3e),
+    ("XF86Copy", 0x1008ff57), ("XF86Cut", 0x1008ff58), ("XF86Display", 0x1008ff59), ("XF86Explorer", 0x1008ff5d),
+    ("XF86Paste", 0x1008ff6d), ("XF86Tools", 0x1008ff81), ("XF86AudioForward", 0x1008ff97),
+    ("XF86TouchpadToggle", 0x1008ffa9), ("XF86AudioMicMute", 0x1008ffb2),
+];
+
+// Modifiers that select a keysym of a keycode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub shift: bool,
+    pub altgr: bool,
+}
+
+// Columns of a keycode's keysyms: group 1 plain and shifted, group 2 (ignored), then AltGr plain and shifted
+const LEVEL_COLUMNS: [(usize, Level); 4] = [
+    (0, Level { shift: false, altgr: false }),
+    (1, Level { shift: true, altgr: false }),
+    (4, Level { shift: false, altgr: true }),
+    (5, Level { shift: true, altgr: true }),
+];
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    min_keycode: u8,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<u32>,  // keysyms_per_keycode entries per keycode from min_keycode on
+}
+
+impl Keymap {
+    pub fn new(min_keycode: u8, keysyms_per_keycode: u8, keysyms: Vec<u32>) -> Self {
+        Keymap { min_keycode, keysyms_per_keycode: keysyms_per_keycode.max(1) as usize, keysyms }
+    }
+
+    // Current mapping of the X server
+    pub fn read(conn: &impl Connection) -> Result<Self, KeySimError> {
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let reply = conn.get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?.reply()?;
+        Ok(Self::new(min_keycode, reply.keysyms_per_keycode, reply.keysyms))
+    }
+
+    fn keycodes(&self) -> impl Iterator<Item = (u8, &[u32])> {
+        self.keysyms.chunks(self.keysyms_per_keycode)
+            .enumerate()
+            .filter_map(|(index, keysyms)| Some((u8::try_from(self.min_keycode as usize + index).ok()?, keysyms)))
+    }
+
+    // Keycode and modifiers that type the keysym, the plain level of the lowest keycode wins
+    pub fn find(&self, keysym: u32) -> Option<(u8, Level)> {
+        LEVEL_COLUMNS.iter().find_map(|&(column, level)| {
+            self.keycodes()
+                .find(|(_, keysyms)| keysyms.get(column) == Some(&keysym))
+                .map(|(keycode, _)| (keycode, level))
+        })
+    }
+
+    // Keycode of a modifier like SHIFT_L
+    pub fn modifier(&self, keysym: u32) -> Option<u8> {
+        self.keycodes()
+            .find(|(_, keysyms)| keysyms.first() == Some(&keysym))
+            .map(|(keycode, _)| keycode)
+    }
+
+    // Keycode without any keysym, type_text binds characters that are not on the layout to it
+    pub fn spare_keycode(&self) -> Option<u8> {
+        self.keycodes()
+            .filter(|(_, keysyms)| keysyms.iter().all(|&keysym| keysym == NO_SYMBOL))
+            .map(|(keycode, _)| keycode)
+            .last()
+    }
+}
+
+impl Level {
+    fn modifiers(&self) -> usize {
+        self.shift as usize + self.altgr as usize
+    }
+}
+
+// Key that a name resolves to: the keycode and the modifiers that select the keysym
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyStroke {
+    pub keycode: u8,
+    pub level: Level,
+}
+
+// Key names of a keymap (lowercase) for press/release/tap/combo.
+// Keysym names and characters of the layout come first, the evdev names of key_codes fill in the rest.
+#[derive(Clone, Debug)]
+pub struct KeyTable {
+    strokes: HashMap<String, KeyStroke>,
+    keymap: Option<Keymap>,
+    shift: Option<u8>,
+    altgr: Option<u8>,
+}
+
+impl KeyTable {
+    // Without a keymap (no X server) only the evdev names are known, as X keycodes of the evdev driver
+    pub fn new(keymap: Option<&Keymap>) -> Self {
+        let mut strokes = HashMap::new();
+
+        if let Some(keymap) = keymap {
+            for &(name, keysym) in KEYSYM_NAMES {
+                if let Some((keycode, level)) = keymap.find(keysym) {
+                    insert_stroke(&mut strokes, name, KeyStroke { keycode, level });
+                }
+            }
+
+            for (keycode, keysyms) in keymap.keycodes() {
+                for &(column, level) in &LEVEL_COLUMNS {
+                    let Some(c) = keysyms.get(column).copied().and_then(keysym_to_char) else {
+                        continue;
+                    };
+                    insert_stroke(&mut strokes, &c.to_string(), KeyStroke { keycode, level });
+                }
+            }
+        }
+
+        for (code, name, aliases) in key_codes::all_keys() {
+            // XTest only accepts 8-bit keycodes, higher evdev codes cannot be sent
+            let Ok(keycode) = u8::try_from(code + X11_KEYCODE_OFFSET) else {
+                continue;
+            };
+
+            for key_name in std::iter::once(&name).chain(aliases) {
+                strokes.entry(key_name.to_lowercase()).or_insert(KeyStroke { keycode, level: Level { shift: false, altgr: false } });
+            }
+        }
+
+        KeyTable {
+            strokes,
+            keymap: keymap.cloned(),
+            shift: keymap.and_then(|keymap| keymap.modifier(SHIFT_L)),
+            altgr: keymap.and_then(|keymap| keymap.modifier(ISO_LEVEL3_SHIFT)),
+        }
+    }
+
+    // Keycodes to hold down for a key name, the modifiers first: "semicolon" is Shift + comma on a German layout.
+    // Besides the names of the table keysyms can be given as "U20AC" (or "u20ac") or "0x1008ff14".
+    pub fn keycodes(&self, name: &str) -> Option<Vec<u8>> {
+        let stroke = match self.strokes.get(&name.to_lowercase()) {
+            Some(stroke) => *stroke,
+            None => {
+                let (keycode, level) = self.keymap.as_ref()?.find(parse_keysym(name)?)?;
+                KeyStroke { keycode, level }
+            },
+        };
+
+        let mut keycodes = Vec::new();
+        if stroke.level.shift {
+            keycodes.push(self.shift?);
+        }
+        if stroke.level.altgr {
+            keycodes.push(self.altgr?);
+        }
+        keycodes.push(stroke.keycode);
+        Some(keycodes)
+    }
+
+    // Names that need no modifier, with their keycode (for the KEY_ constants of Lua)
+    pub fn plain_keys(&self) -> impl Iterator<Item = (&str, u8)> {
+        self.strokes.iter()
+            .filter(|(_, stroke)| stroke.level.modifiers() == 0)
+            .map(|(name, stroke)| (name.as_str(), stroke.keycode))
+    }
+}
+
+// Names differing only in case ("a" and "A") share an entry, the key with fewer modifiers keeps it
+fn insert_stroke(strokes: &mut HashMap<String, KeyStroke>, name: &str, stroke: KeyStroke) {
+    let name = name.to_lowercase();
+    match strokes.get(&name) {
+        Some(existing) if existing.level.modifiers() <= stroke.level.modifiers() => {},
+        _ => { strokes.insert(name, stroke); },
+    }
+}
+
+// "U20AC" / "u20ac" / "U+20AC" (Unicode code point) or "0x1008ff14" (keysym value)
+fn parse_keysym(name: &str) -> Option<u32> {
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    let hex = name.strip_prefix(['U', 'u'])?;
+    let code = u32::from_str_radix(hex.strip_prefix('+').unwrap_or(hex), 16).ok()?;
+    char::from_u32(code).map(char_to_keysym)
+}
+
+// Printable character of a keysym, the reverse of char_to_keysym
+fn keysym_to_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x21..=0x7e | 0xa1..=0xff => char::from_u32(keysym),
+        EURO_SIGN => Some('€'),
+        _ if keysym > UNICODE_KEYSYM_OFFSET => char::from_u32(keysym - UNICODE_KEYSYM_OFFSET).filter(|c| !c.is_control()),
+        _ => None,
+    }
+}
+
+// Key table of the X server's current mapping, replaced by the watcher thread on every MappingNotify
+static KEY_TABLE: RwLock<Option<Arc<KeyTable>>> = RwLock::new(None);
+static WATCHER: Once = Once::new();
+
+// Current key table of the X server, None without an X server. The first call reads the mapping and starts the watcher.
+pub fn key_table() -> Option<Arc<KeyTable>> {
+    WATCHER.call_once(watch_mapping);
+    KEY_TABLE.read().ok().and_then(|table| table.clone())
+}
+
+fn store_key_table(keymap: &Keymap) {
+    if let Ok(mut table) = KEY_TABLE.write() {
+        *table = Some(Arc::new(KeyTable::new(Some(keymap))));
+    }
+}
+
+fn watch_mapping() {
+    let Ok((conn, _)) = x11rb::connect(None) else {
+        eprintln!("No connection to the X server, key names use the evdev keycodes");
+        return;
+    };
+
+    match Keymap::read(&conn) {
+        Ok(keymap) => store_key_table(&keymap),
+        Err(e) => {
+            eprintln!("Could not read the keyboard mapping: {}", e);
+            return;
+        },
+    }
+
+    let spawned = thread::Builder::new().name("keymap-watcher".to_string()).spawn(move || {
+        // MappingNotify is sent to every client, no event mask needed
+        while let Ok(event) = conn.wait_for_event() {
+            let Event::MappingNotify(notify) = event else {
+                continue;
+            };
+            if notify.request != Mapping::KEYBOARD {
+                continue;
+            }
+
+            // Also sent for the spare keycode of type_text, the table is small enough to rebuild each time
+            match Keymap::read(&conn) {
+                Ok(keymap) => store_key_table(&keymap),
+                Err(e) => eprintln!("Could not read the keyboard mapping: {}", e),
+            }
+        }
+    });
+
+    if let Err(e) = spawned {
+        eprintln!("Could not start the keymap watcher: {}", e);
+    }
+}
+
+// Keysym of a character. Layouts use the old keysyms for Latin-1 and the Euro sign, the Unicode keysym for the rest.
+pub fn char_to_keysym(c: char) -> u32 {
+    match c {
+        '\n' | '\r' => RETURN,
+        '\t' => TAB,
+        '\u{8}' => BACKSPACE,
+        '€' => EURO_SIGN,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u32,
+        _ => UNICODE_KEYSYM_OFFSET + c as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Part of a German layout as the X server reports it (evdev keycodes, 6 keysyms per keycode)
+    fn german_keymap() -> Keymap {
+        let mut keysyms = vec![NO_SYMBOL; 6 * 248];
+        let mut set = |keycode: usize, syms: &[u32]| {
+            let start = (keycode - 8) * 6;
+            keysyms[start..start + syms.len()].copy_from_slice(syms);
+        };
+        set(24, &[0x71, 0x51, 0, 0, 0x40, 0]);              // q Q, AltGr @
+        set(26, &[0x65, 0x45, 0, 0, EURO_SIGN, 0]);         // e E, AltGr €
+        set(29, &[0x7a, 0x5a]);                             // z Z
+        set(50, &[SHIFT_L]);
+        set(52, &[0x79, 0x59]);                             // y Y
+        set(59, &[0x2c, 0x3b]);                             // comma semicolon
+        set(108, &[ISO_LEVEL3_SHIFT]);
+        set(121, &[0x1008ff12]);                            // XF86AudioMute
+        set(172, &[0x1008ff14]);                            // XF86AudioPlay
+        Keymap::new(8, 6, keysyms)
+    }
+
+    #[test]
+    fn letters_follow_the_layout() {
+        let table = KeyTable::new(Some(&german_keymap()));
+        assert_eq!(table.keycodes("z"), Some(vec![29]));
+        assert_eq!(table.keycodes("y"), Some(vec![52]));
+        assert_eq!(table.keycodes("Z"), Some(vec![29]));
+    }
+
+    #[test]
+    fn characters_on_other_levels_get_their_modifiers() {
+        let table = KeyTable::new(Some(&german_keymap()));
+        assert_eq!(table.keycodes("semicolon"), Some(vec![50, 59]));
+        assert_eq!(table.keycodes(";"), Some(vec![50, 59]));
+        assert_eq!(table.keycodes("at"), Some(vec![108, 24]));
+        assert_eq!(table.keycodes("@"), Some(vec![108, 24]));
+        assert!(table.plain_keys().any(|(name, keycode)| name == "comma" && keycode == 59));
+        assert!(!table.plain_keys().any(|(name, _)| name == "semicolon"));
+    }
+
+    #[test]
+    fn keysyms_by_name_and_value() {
+        let table = KeyTable::new(Some(&german_keymap()));
+        assert_eq!(table.keycodes("XF86AudioMute"), Some(vec![121]));
+        assert_eq!(table.keycodes("xf86audiomute"), Some(vec![121]));
+        assert_eq!(table.keycodes("0x1008ff14"), Some(vec![172]));
+        assert_eq!(table.keycodes("U20AC"), Some(vec![108, 26]));
+        assert_eq!(table.keycodes("u20ac"), Some(vec![108, 26]));
+        assert_eq!(table.keycodes("U+20AC"), Some(vec![108, 26]));
+        assert_eq!(table.keycodes("U+00A7"), None);  // § is not in this keymap
+        assert_eq!(table.keycodes("no such key"), None);
+    }
+
+    #[test]
+    fn evdev_names_without_a_keymap() {
+        let table = KeyTable::new(None);
+        assert_eq!(table.keycodes("z"), Some(vec![44 + 8]));
+        assert_eq!(table.keycodes("y"), Some(vec![21 + 8]));
+        assert_eq!(table.keycodes("ctrl"), Some(vec![29 + 8]));
+        assert_eq!(table.keycodes("U20AC"), None);
+        assert_eq!(table.keycodes("0x1008ff14"), None);
+    }
+}
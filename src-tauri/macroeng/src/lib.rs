@@ -1,9 +1,30 @@
 mod keyboard_trigger;
 pub use keyboard_trigger::KeyboardTrigger;
+mod output;
+pub mod keymap;
+pub use output::{MouseButton, OutputAction, OutputBackend, OutputEvent, OutputSink, RecordingSink, UinputSink, XTestSink};
 
 #[path ="keyboard_listener.rs"]
 pub mod KeyboardListener;
 
+pub mod key_codes;
+
+mod evdev;
+mod hotplug;
+mod poller;
+mod axis;
+mod input_source;
+mod recorder;
+mod uinput;
+pub use input_source::{InputSource, InputEvent, SourceEvent, EvdevSource, ReplaySource};
+
+mod gesture;
+pub use gesture::{Gesture, GestureFilter, GestureInfo, GestureRecognizer, GestureTimings, Gestures};
+mod chord;
+pub use chord::{ChordFilter, ChordRecognizer};
+mod sequence;
+pub use sequence::{SequenceFilter, SequenceRecognizer, SequenceStatus, SequenceStep};
+
 mod lua_manager;
 pub use lua_manager::LuaManager;
 
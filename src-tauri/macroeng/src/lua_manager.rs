@@ -1,22 +1,31 @@
 use std::error::Error;
 use std::process::Command;
 use std::sync::Arc;
-use std::collections::HashMap;
 use crate::keyboard_trigger::KeyboardTrigger;
+use crate::output::{MouseButton, OutputBackend, OutputSink};
+use crate::keymap::{self, KeyTable};
+use crate::key_codes;
+use crate::gesture::Gesture;
 use mlua::prelude::*;
 use clipboard::{ClipboardContext, ClipboardProvider};
 
 pub struct LuaManager {
     script: Arc<KeyboardTrigger>,
     lua: Lua,
-    key_map: HashMap<String, u8>,
+    key_map: Arc<KeyTable>,
 }
 
 impl LuaManager {
+    // Sends keys through the backend chosen with OutputBackend::set_default
     pub fn new() -> Result<Self, Box<dyn Error>> {
-        let script = Arc::new(KeyboardTrigger::new()?);
+        Self::with_output(OutputBackend::default_backend().open()?)
+    }
+
+    // Sends keys to the given sink, e.g. a RecordingSink to run scripts without a display
+    pub fn with_output(output: Arc<dyn OutputSink>) -> Result<Self, Box<dyn Error>> {
+        let key_map = Self::create_key_map(output.as_ref());
+        let script = Arc::new(KeyboardTrigger::with_output(output));
         let lua = Lua::new();
-        let key_map = Self::create_key_map();
 
         let lua_script = LuaManager { script, lua, key_map };
         lua_script.register_lua_functions()?;
@@ -24,68 +33,16 @@ impl LuaManager {
         Ok(lua_script)
     }
 
-    // Erstellt eine Zuordnung von String-Namen zu Keycodes
-    fn create_key_map() -> HashMap<String, u8> {
-        let mut map = HashMap::new();
-        
-        // Steuerungstasten
-        map.insert("ctrl".to_string(), 37u8);
-        map.insert("alt".to_string(), 64u8);
-        map.insert("shift".to_string(), 50u8);
-        map.insert("enter".to_string(), 36u8);
-        map.insert("space".to_string(), 65u8);
-        map.insert("tab".to_string(), 23u8);
-        map.insert("esc".to_string(), 9u8);
-        
-        // Buchstaben
-        map.insert("a".to_string(), 38u8);
-        map.insert("b".to_string(), 56u8);
-        map.insert("c".to_string(), 54u8);
-        map.insert("d".to_string(), 40u8);
-        map.insert("e".to_string(), 26u8);
-        map.insert("f".to_string(), 41u8);
-        map.insert("g".to_string(), 42u8);
-        map.insert("h".to_string(), 43u8);
-        map.insert("i".to_string(), 31u8);
-        map.insert("j".to_string(), 44u8);
-        map.insert("k".to_string(), 45u8);
-        map.insert("l".to_string(), 46u8);
-        map.insert("m".to_string(), 58u8);
-        map.insert("n".to_string(), 57u8);
-        map.insert("o".to_string(), 32u8);
-        map.insert("p".to_string(), 33u8);
-        map.insert("q".to_string(), 24u8);
-        map.insert("r".to_string(), 27u8);
-        map.insert("s".to_string(), 39u8);
-        map.insert("t".to_string(), 28u8);
-        map.insert("u".to_string(), 30u8);
-        map.insert("v".to_string(), 55u8);
-        map.insert("w".to_string(), 25u8);
-        map.insert("x".to_string(), 53u8);
-        map.insert("y".to_string(), 29u8);
-        map.insert("z".to_string(), 52u8);
-
-        // Ziffern
-        map.insert("0".to_string(), 19u8);
-        map.insert("1".to_string(), 10u8);
-        map.insert("2".to_string(), 11u8);
-        map.insert("3".to_string(), 12u8);
-        map.insert("4".to_string(), 13u8);
-        map.insert("5".to_string(), 14u8);
-        map.insert("6".to_string(), 15u8);
-        map.insert("7".to_string(), 16u8);
-        map.insert("8".to_string(), 17u8);
-        map.insert("9".to_string(), 18u8);
-
-        // Weitere Tasten können nach Bedarf hinzugefügt werden
-        
-        map
+    // Tastennamen über die aktuelle Belegung des X-Servers auflösen (wird bei MappingNotify neu aufgebaut).
+    // Ohne X-Server die Belegung der Ausgabe (z.B. RecordingSink::with_keymap), sonst nur der evdev-Tastenkatalog.
+    fn create_key_map(output: &dyn OutputSink) -> Arc<KeyTable> {
+        keymap::key_table()
+            .unwrap_or_else(|| Arc::new(KeyTable::new(output.keymap().as_ref())))
     }
 
-    // Hilfsfunktion zum Konvertieren von String zu Keycode
-    fn key_to_code(&self, key: &str) -> Result<u8, String> {
-        self.key_map.get(&key.to_lowercase())
-            .copied()
+    // Hilfsfunktion zum Konvertieren von String zu Keycodes (Modifier zuerst)
+    fn key_to_code(&self, key: &str) -> Result<Vec<u8>, String> {
+        self.key_map.keycodes(key)
             .ok_or_else(|| format!("Unbekannte Taste: '{}'", key))
     }
 
@@ -135,6 +92,22 @@ impl LuaManager {
         error_message
     }
 
+    // Stellt dem Skript den auslösenden Trigger als Tabelle `trigger` zur Verfügung:
+    // trigger.key, trigger.device, trigger.delta (Schritte von Mausrad/Drehregler, 0 bei Tasten)
+    // und trigger.gesture ("tap", "doubletap" oder "hold"), trigger.layer ist die aktive Ebene (nil = Basisebene)
+    pub fn set_trigger(&self, key: &str, device: &str, delta: i32, gesture: Gesture, layer: Option<&str>) -> Result<(), String> {
+        let set = || -> LuaResult<()> {
+            let trigger = self.lua.create_table()?;
+            trigger.set("key", key)?;
+            trigger.set("device", device)?;
+            trigger.set("delta", delta)?;
+            trigger.set("gesture", gesture.name())?;
+            trigger.set("layer", layer)?;
+            self.lua.globals().set("trigger", trigger)
+        };
+        set().map_err(|e| e.to_string())
+    }
+
     pub fn run_script_with_name(&self, content: &str, name: &str) -> Result<(), String> {
         self.lua.load(content)
             .set_name(name)
@@ -155,9 +128,12 @@ impl LuaManager {
             match key {
                 LuaValue::String(s) => {
                     let key_str = s.to_str().map_err(|e| mlua::Error::external(e))?;
-                    let keycode = key_map_ref.get(&key_str.to_lowercase())
+                    let keycodes = key_map_ref.keycodes(&key_str)
                         .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?;
-                    script_ref.press(*keycode).map_err(|e| mlua::Error::external(e))
+                    for keycode in keycodes {
+                        script_ref.press(keycode).map_err(|e| mlua::Error::external(e))?;
+                    }
+                    Ok(())
                 },
                 LuaValue::Integer(i) => {
                     if i < 0 || i > 255 {
@@ -178,9 +154,13 @@ impl LuaManager {
             match key {
                 LuaValue::String(s) => {
                     let key_str = s.to_str().map_err(|e| mlua::Error::external(e))?;
-                    let keycode = key_map_ref.get(&key_str.to_lowercase())
+                    let keycodes = key_map_ref.keycodes(&key_str)
                         .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?;
-                    script_ref.release(*keycode).map_err(|e| mlua::Error::external(e))
+                    // Modifier zuletzt loslassen
+                    for keycode in keycodes.into_iter().rev() {
+                        script_ref.release(keycode).map_err(|e| mlua::Error::external(e))?;
+                    }
+                    Ok(())
                 },
                 LuaValue::Integer(i) => {
                     if i < 0 || i > 255 {
@@ -358,23 +338,27 @@ globals.set("clipboard", self.lua.create_function(move |lua_ctx, _: ()| {
                 }
             }).transpose()?.flatten();
             
-            // Keycode ermitteln
-            let keycode = match key {
+            // Keycodes ermitteln (Taste mit Modifiern, z.B. Shift für "semicolon" auf deutscher Belegung)
+            let keycodes = match key {
                 LuaValue::String(s) => {
                     let key_str = s.to_str().map_err(|e| mlua::Error::external(e))?;
-                    *key_map_ref.get(&key_str.to_lowercase())
+                    key_map_ref.keycodes(&key_str)
                         .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?
                 },
                 LuaValue::Integer(i) => {
                     if i < 0 || i > 255 {
                         return Err(mlua::Error::external(format!("Keycode muss zwischen 0 und 255 sein: {}", i)));
                     }
-                    i as u8
+                    vec![i as u8]
                 },
                 _ => return Err(mlua::Error::external("Taste muss ein String oder ein Keycode (Zahl) sein")),
             };
             
-            script_ref.tap(keycode, delay).map_err(|e| mlua::Error::external(e))
+            let result = match keycodes.as_slice() {
+                [keycode] => script_ref.tap(*keycode, delay),
+                _ => script_ref.combo(&keycodes, Some(delay.unwrap_or(50))),
+            };
+            result.map_err(|e| mlua::Error::external(e))
         })?)?;
 
         // Wait function - bleibt unverändert
@@ -410,28 +394,33 @@ globals.set("clipboard", self.lua.create_function(move |lua_ctx, _: ()| {
                     let mut codes = Vec::new();
                     for pair in t.pairs::<i32, LuaValue>() {
                         let (_, key_value) = pair?;
-                        let code = match key_value {
+                        let keys = match key_value {
                             LuaValue::String(s) => {
                                 let key_str = s.to_str().map_err(|e| mlua::Error::external(e))?;
-                                *key_map_ref.get(&key_str.to_lowercase())
+                                key_map_ref.keycodes(&key_str)
                                     .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?
                             },
                             LuaValue::Integer(i) => {
                                 if i < 0 || i > 255 {
                                     return Err(mlua::Error::external(format!("Keycode muss zwischen 0 und 255 sein: {}", i)));
                                 }
-                                i as u8
+                                vec![i as u8]
                             },
                             _ => return Err(mlua::Error::external("Taste in der Tabelle muss ein String oder ein Keycode (Zahl) sein")),
                         };
-                        codes.push(code);
+                        // Modifier, die mehrere Tasten brauchen, nur einmal drücken
+                        for keycode in keys {
+                            if !codes.contains(&keycode) {
+                                codes.push(keycode);
+                            }
+                        }
                     }
                     codes
                 },
                 LuaValue::String(s) => {
                     let key_str = s.to_str().map_err(|e| mlua::Error::external(e))?;
-                    vec![*key_map_ref.get(&key_str.to_lowercase())
-                        .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?]
+                    key_map_ref.keycodes(&key_str)
+                        .ok_or_else(|| mlua::Error::external(format!("Unbekannte Taste: '{}'", key_str)))?
                 },
                 LuaValue::Integer(i) => {
                     if i < 0 || i > 255 {
@@ -456,10 +445,50 @@ globals.set("clipboard", self.lua.create_function(move |lua_ctx, _: ()| {
                 .map_err(|e| mlua::Error::external(e))
         })?)?;
 
-        // Tastennamen als Lua-Konstanten registrieren
-        for (key_name, key_code) in &self.key_map {
+        // Text im Tastaturlayout des Systems tippen: type_text("Hallo, Welt! €") oder type_text(text, 30) mit 30 ms pro Zeichen
+        let script_ref = self.script.clone();
+        globals.set("type_text", self.lua.create_function(move |_, (text, delay): (String, Option<u64>)| {
+            script_ref.type_text(&text, delay).map_err(|e| mlua::Error::external(e))
+        })?)?;
+
+        // Mausklick: click(), click("right") oder click("left", 100) mit Haltezeit in ms
+        let script_ref = self.script.clone();
+        globals.set("click", self.lua.create_function(move |_, (button, delay): (Option<String>, Option<u64>)| {
+            let button = match button {
+                Some(name) => MouseButton::from_name(&name)
+                    .ok_or_else(|| mlua::Error::external(format!("Unbekannte Maustaste: '{}'", name)))?,
+                None => MouseButton::Left,
+            };
+            script_ref.click(button, delay).map_err(|e| mlua::Error::external(e))
+        })?)?;
+
+        // Mauszeiger relativ bewegen: mouse_move(dx, dy)
+        let script_ref = self.script.clone();
+        globals.set("mouse_move", self.lua.create_function(move |_, (dx, dy): (i32, i32)| {
+            script_ref.mouse_move(dx, dy).map_err(|e| mlua::Error::external(e))
+        })?)?;
+
+        // Mausrad: scroll(3) nach oben, scroll(-3) nach unten
+        let script_ref = self.script.clone();
+        globals.set("scroll", self.lua.create_function(move |_, steps: i32| {
+            script_ref.scroll(steps).map_err(|e| mlua::Error::external(e))
+        })?)?;
+
+        // LED der blockierten Geräte schalten: led("caps", true) oder led("num", false, "Gerätename").
+        // Gibt die Anzahl der Geräte zurück, an denen die LED geschaltet wurde.
+        globals.set("led", self.lua.create_function(move |_, (name, on, device): (String, bool, Option<String>)| {
+            let code = key_codes::string_to_led(&name)
+                .ok_or_else(|| mlua::Error::external(format!("Unbekannte LED: '{}'", name)))?;
+            Ok(crate::KeyboardListener::Instance::new().set_led(code, on, device.as_deref()))
+        })?)?;
+
+        // Tastennamen als Lua-Konstanten registrieren (nur Namen, die gültige Lua-Bezeichner ergeben und keinen Modifier brauchen)
+        for (key_name, key_code) in self.key_map.plain_keys() {
+            if !key_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                continue;
+            }
             let uppercase_name = format!("KEY_{}", key_name.to_uppercase());
-            globals.set(uppercase_name, *key_code)?;
+            globals.set(uppercase_name, key_code)?;
         }
 
         Ok(())
@@ -468,4 +497,95 @@ globals.set("clipboard", self.lua.create_function(move |lua_ctx, _: ()| {
     pub fn run_script(&self, lua_code: &str) -> LuaResult<()> {
         self.lua.load(lua_code).exec()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Keymap;
+    use crate::output::RecordingSink;
+
+    // Layout for type_text: keycode 9 a/A, 10 Shift_L, 11 AltGr, 12 e/E/€ with AltGr
+    fn test_keymap() -> Keymap {
+        let mut keysyms = vec![0u32; 6 * 6];
+        keysyms[6..12].copy_from_slice(&[0x61, 0x41, 0, 0, 0, 0]);
+        keysyms[12] = keymap::SHIFT_L;
+        keysyms[18] = keymap::ISO_LEVEL3_SHIFT;
+        keysyms[24..30].copy_from_slice(&[0x65, 0x45, 0, 0, 0x20ac, 0]);
+        Keymap::new(8, 6, keysyms)
+    }
+
+    fn recording_manager() -> (LuaManager, Arc<RecordingSink>) {
+        let sink = Arc::new(RecordingSink::new().with_keymap(test_keymap()));
+        let manager = LuaManager::with_output(sink.clone()).unwrap();
+        (manager, sink)
+    }
+
+    // Trace without the times, e.g. "key 38 down"
+    fn actions(sink: &RecordingSink) -> Vec<String> {
+        sink.trace().lines()
+            .map(|line| line.split_once(" ms ").map_or(line, |(_, action)| action).to_string())
+            .collect()
+    }
+
+    // Keycode a name is sent as. Names follow the X layout if there is one, so the tests don't hard-code them.
+    fn code(manager: &LuaManager, key: &str) -> u8 {
+        *manager.key_to_code(key).unwrap().last().unwrap()
+    }
+
+    #[test]
+    fn tap_presses_and_releases() {
+        let (manager, sink) = recording_manager();
+        let a = code(&manager, "a");
+
+        manager.run_script("tap('a', 0)\ntap(KEY_A, 0)").unwrap();
+        let tap = [format!("key {} down", a), format!("key {} up", a)];
+        assert_eq!(actions(&sink), [tap.clone(), tap].concat());
+    }
+
+    #[test]
+    fn combo_releases_in_reverse_order() {
+        let (manager, sink) = recording_manager();
+        let (ctrl, c) = (code(&manager, "ctrl"), code(&manager, "c"));
+
+        manager.run_script("combo({'ctrl', 'c'}, 0)").unwrap();
+        assert_eq!(actions(&sink), [
+            format!("key {} down", ctrl),
+            format!("key {} down", c),
+            format!("key {} up", c),
+            format!("key {} up", ctrl),
+        ]);
+    }
+
+    #[test]
+    fn press_and_release_are_sent_separately() {
+        let (manager, sink) = recording_manager();
+        let (shift, a) = (code(&manager, "shift"), code(&manager, "a"));
+
+        manager.run_script("press('shift')\npress('a')").unwrap();
+        assert_eq!(actions(&sink), [format!("key {} down", shift), format!("key {} down", a)]);
+
+        sink.clear();
+        manager.run_script("release('a')\nrelease('shift')").unwrap();
+        assert_eq!(actions(&sink), [format!("key {} up", a), format!("key {} up", shift)]);
+    }
+
+    #[test]
+    fn type_text_uses_the_layout_of_the_output() {
+        let (manager, sink) = recording_manager();
+
+        manager.run_script("type_text('aA€', 0)").unwrap();
+        assert_eq!(actions(&sink), [
+            "key 9 down", "key 9 up",
+            "key 10 down", "key 9 down", "key 9 up", "key 10 up",
+            "key 11 down", "key 12 down", "key 12 up", "key 11 up",
+        ]);
+    }
+
+    #[test]
+    fn unknown_key_sends_nothing() {
+        let (manager, sink) = recording_manager();
+
+        assert!(manager.run_script("tap('no such key')").is_err());
+        assert!(sink.events().is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+// output.rs
+// Where synthetic key and mouse input goes: X11 XTest, a uinput virtual device, or an in-memory recording.
+// KeyboardTrigger adds the timing (tap, combo) on top of an OutputSink, so scripts can run against
+// RecordingSink without a display, e.g. to test or trace a macro.
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::ConnectionExt as XProtoConnectionExt;
+use x11rb::protocol::xtest::ConnectionExt as XTestConnectionExt;
+
+use crate::evdev::{EV_KEY, EV_REL};
+use crate::key_codes;
+use crate::keyboard_trigger::KeySimError;
+use crate::keymap::{Keymap, NO_SYMBOL};
+use crate::uinput::VirtualDevice;
+
+// X11 Event Types
+const KEY_PRESS: u8 = 2;
+const KEY_RELEASE: u8 = 3;
+const BUTTON_PRESS: u8 = 4;
+const BUTTON_RELEASE: u8 = 5;
+const MOTION_NOTIFY: u8 = 6;
+
+// X11 buttons 4 and 5 are the scroll wheel
+const X11_SCROLL_UP: u8 = 4;
+const X11_SCROLL_DOWN: u8 = 5;
+
+// X11 keycodes of the evdev driver are the Linux key codes shifted by 8
+const X11_KEYCODE_OFFSET: u8 = 8;
+
+// Key codes from BTN_MISC on are buttons, a device with buttons is no keyboard for some compositors
+const BTN_MISC: u16 = 0x100;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+
+// Time a compositor needs to pick up a new virtual device, input sent earlier gets lost
+const UINPUT_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// Time X clients need to pick up a changed keyboard mapping (MappingNotify)
+const REMAP_SETTLE_TIME: Duration = Duration::from_millis(20);
+
+// Where press, release, tap and combo send their keys, saved in settings.json
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputBackend {
+    #[default]
+    Auto,    // XTest on an X11 session, uinput on Wayland or without X server
+    XTest,   // X11 XTest extension, reaches X11 applications only
+    Uinput,  // Virtual keyboard through /dev/uinput, works with every compositor (needs root)
+}
+
+static OUTPUT_BACKEND: RwLock<OutputBackend> = RwLock::new(OutputBackend::Auto);
+
+impl OutputBackend {
+    // Backend for all following KeyboardTrigger::new() and LuaManager::new()
+    pub fn set_default(backend: OutputBackend) {
+        if let Ok(mut current) = OUTPUT_BACKEND.write() {
+            *current = backend;
+        }
+    }
+
+    pub fn default_backend() -> OutputBackend {
+        OUTPUT_BACKEND.read().map(|backend| *backend).unwrap_or_default()
+    }
+
+    // Opens the sink of this backend, Auto picks XTest or uinput
+    pub fn open(self) -> Result<Arc<dyn OutputSink>, KeySimError> {
+        Ok(match self {
+            OutputBackend::XTest => Arc::new(XTestSink::connect()?),
+            OutputBackend::Uinput => Arc::new(UinputSink::open()?),
+            // XWayland has XTest too, but its keys only reach X11 applications
+            OutputBackend::Auto => match XTestSink::connect() {
+                Ok(sink) if !is_wayland_session() => Arc::new(sink),
+                _ => Arc::new(UinputSink::open()?),
+            },
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "left" => Some(MouseButton::Left),
+            "middle" => Some(MouseButton::Middle),
+            "right" => Some(MouseButton::Right),
+            _ => None,
+        }
+    }
+
+    fn x11_button(self) -> u8 {
+        match self {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+        }
+    }
+
+    fn evdev_code(self) -> u16 {
+        match self {
+            MouseButton::Left => 0x110,   // BTN_LEFT
+            MouseButton::Right => 0x111,  // BTN_RIGHT
+            MouseButton::Middle => 0x112, // BTN_MIDDLE
+        }
+    }
+}
+
+// Receiver of synthetic input. Keycodes are X11 keycodes (Linux key code + 8) for every sink.
+pub trait OutputSink: Send + Sync {
+    fn key(&self, keycode: u8, pressed: bool) -> Result<(), KeySimError>;
+
+    fn button(&self, button: MouseButton, pressed: bool) -> Result<(), KeySimError>;
+
+    // Relative pointer movement in pixels
+    fn move_pointer(&self, dx: i32, dy: i32) -> Result<(), KeySimError>;
+
+    // Scroll wheel steps, positive = up
+    fn scroll(&self, steps: i32) -> Result<(), KeySimError>;
+
+    // Sends buffered input, sinks that write right away have nothing to do
+    fn flush(&self) -> Result<(), KeySimError> {
+        Ok(())
+    }
+
+    // Keyboard layout the keys end up on, used by type_text. None if it is unknown.
+    fn keymap(&self) -> Option<Keymap> {
+        None
+    }
+
+    // Binds a keysym to a spare keycode while type_text needs it, NO_SYMBOL unbinds it again
+    fn remap(&self, _keycode: u8, _keysym: u32) -> Result<(), KeySimError> {
+        Err("Characters that are not on the keyboard layout cannot be typed with this output".into())
+    }
+
+    // Whether remap works, type_text checks it before it types the first character
+    fn can_remap(&self) -> bool {
+        false
+    }
+}
+
+// ====== XTest ======
+pub struct XTestSink {
+    conn: x11rb::rust_connection::RustConnection,
+}
+
+impl XTestSink {
+    // Connection to an X server with the XTest extension
+    pub fn connect() -> Result<Self, KeySimError> {
+        let (conn, _) = x11rb::connect(None)
+            .map_err(|e| KeySimError::OtherError(format!("Failed to connect to X11 server: {}", e)))?;
+        if conn.extension_information(x11rb::protocol::xtest::X11_EXTENSION_NAME)?.is_none() {
+            return Err("X11 server has no XTest extension".into());
+        }
+        Ok(XTestSink { conn })
+    }
+
+    fn fake_input(&self, event_type: u8, detail: u8, x: i32, y: i32) -> Result<(), KeySimError> {
+        let x = x.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let y = y.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.conn.xtest_fake_input(event_type, detail, 0, 0, x, y, 0)?;
+        Ok(())
+    }
+}
+
+impl OutputSink for XTestSink {
+    fn key(&self, keycode: u8, pressed: bool) -> Result<(), KeySimError> {
+        self.fake_input(if pressed { KEY_PRESS } else { KEY_RELEASE }, keycode, 0, 0)
+    }
+
+    fn button(&self, button: MouseButton, pressed: bool) -> Result<(), KeySimError> {
+        self.fake_input(if pressed { BUTTON_PRESS } else { BUTTON_RELEASE }, button.x11_button(), 0, 0)?;
+        self.flush()
+    }
+
+    fn move_pointer(&self, dx: i32, dy: i32) -> Result<(), KeySimError> {
+        // detail 1 = relative to the current position
+        self.fake_input(MOTION_NOTIFY, 1, dx, dy)?;
+        self.flush()
+    }
+
+    fn scroll(&self, steps: i32) -> Result<(), KeySimError> {
+        let button = if steps > 0 { X11_SCROLL_UP } else { X11_SCROLL_DOWN };
+        for _ in 0..steps.unsigned_abs() {
+            self.fake_input(BUTTON_PRESS, button, 0, 0)?;
+            self.fake_input(BUTTON_RELEASE, button, 0, 0)?;
+        }
+        self.flush()
+    }
+
+    fn flush(&self) -> Result<(), KeySimError> {
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn keymap(&self) -> Option<Keymap> {
+        Keymap::read(&self.conn).ok()
+    }
+
+    fn remap(&self, keycode: u8, keysym: u32) -> Result<(), KeySimError> {
+        // Same keysym with and without shift, so a held shift does not change it
+        self.conn.change_keyboard_mapping(1, keycode, 2, &[keysym, keysym])?;
+        // Round trip: the server has applied the mapping before the key is sent
+        self.conn.get_input_focus()?.reply()?;
+        thread::sleep(REMAP_SETTLE_TIME);
+        Ok(())
+    }
+
+    fn can_remap(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for XTestSink {
+    fn drop(&mut self) {
+        // Ensure all pending events are sent
+        let _ = self.conn.flush();
+    }
+}
+
+// ====== uinput ======
+// The virtual devices are created once and shared by all scripts, the compositor only has to pick them up once.
+// The mouse is only created when a script uses it.
+pub struct UinputSink {
+    keyboard: &'static Mutex<VirtualDevice>,
+}
+
+impl UinputSink {
+    pub fn open() -> Result<Self, KeySimError> {
+        Ok(UinputSink { keyboard: virtual_keyboard()? })
+    }
+}
+
+impl OutputSink for UinputSink {
+    fn key(&self, keycode: u8, pressed: bool) -> Result<(), KeySimError> {
+        let code = keycode.checked_sub(X11_KEYCODE_OFFSET)
+            .ok_or_else(|| format!("Keycode {} has no Linux key code", keycode))?;
+        emit(self.keyboard, &[(EV_KEY, code as u16, pressed as i32)])
+    }
+
+    fn button(&self, button: MouseButton, pressed: bool) -> Result<(), KeySimError> {
+        emit(virtual_mouse()?, &[(EV_KEY, button.evdev_code(), pressed as i32)])
+    }
+
+    fn move_pointer(&self, dx: i32, dy: i32) -> Result<(), KeySimError> {
+        emit(virtual_mouse()?, &[(EV_REL, REL_X, dx), (EV_REL, REL_Y, dy)])
+    }
+
+    fn scroll(&self, steps: i32) -> Result<(), KeySimError> {
+        emit(virtual_mouse()?, &[(EV_REL, key_codes::REL_WHEEL, steps)])
+    }
+
+    // The layout of the X server (or XWayland, which follows the compositor's layout).
+    // No remap: the compositor does not see changes of the X mapping.
+    fn keymap(&self) -> Option<Keymap> {
+        let (conn, _) = x11rb::connect(None).ok()?;
+        Keymap::read(&conn).ok()
+    }
+}
+
+// Sends events as one report
+fn emit(device: &Mutex<VirtualDevice>, events: &[(u16, u16, i32)]) -> Result<(), KeySimError> {
+    let mut device = device.lock().map_err(|_| "Virtual device is poisoned")?;
+    for &(type_, code, value) in events {
+        device.emit(type_, code, value)?;
+    }
+    device.sync()?;
+    Ok(())
+}
+
+fn virtual_keyboard() -> Result<&'static Mutex<VirtualDevice>, KeySimError> {
+    static KEYBOARD: OnceLock<Mutex<VirtualDevice>> = OnceLock::new();
+
+    shared_device(&KEYBOARD, || {
+        // Every key that has an X11 keycode
+        let keys: Vec<u16> = key_codes::all_keys()
+            .map(|(code, _, _)| code)
+            .filter(|&code| code > 0 && code < BTN_MISC && code + (X11_KEYCODE_OFFSET as u16) <= u8::MAX as u16)
+            .collect();
+        VirtualDevice::create("keyboard", &keys, &[], &[], &[], &[])
+    })
+}
+
+fn virtual_mouse() -> Result<&'static Mutex<VirtualDevice>, KeySimError> {
+    static MOUSE: OnceLock<Mutex<VirtualDevice>> = OnceLock::new();
+
+    shared_device(&MOUSE, || {
+        let buttons = [MouseButton::Left, MouseButton::Middle, MouseButton::Right].map(MouseButton::evdev_code);
+        VirtualDevice::create("mouse", &buttons, &[REL_X, REL_Y, key_codes::REL_WHEEL], &[], &[], &[])
+    })
+}
+
+fn shared_device(
+    device: &'static OnceLock<Mutex<VirtualDevice>>,
+    create: impl FnOnce() -> std::io::Result<VirtualDevice>,
+) -> Result<&'static Mutex<VirtualDevice>, KeySimError> {
+    static CREATING: Mutex<()> = Mutex::new(());
+
+    if let Some(device) = device.get() {
+        return Ok(device);
+    }
+
+    let _creating = CREATING.lock().map_err(|_| "Virtual device is poisoned")?;
+    if let Some(device) = device.get() {
+        return Ok(device);
+    }
+
+    let created = create()?;
+    thread::sleep(UINPUT_SETTLE_TIME);
+    Ok(device.get_or_init(|| Mutex::new(created)))
+}
+
+fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
+// ====== Recording ======
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputAction {
+    Key { keycode: u8, pressed: bool },
+    Button { button: MouseButton, pressed: bool },
+    Move { dx: i32, dy: i32 },
+    Scroll { steps: i32 },
+    Remap { keycode: u8, keysym: u32 },
+}
+
+// A synthetic event and when it was sent, relative to the creation of the RecordingSink
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct OutputEvent {
+    pub at: Duration,
+    pub action: OutputAction,
+}
+
+// One line per event, e.g. "  52 ms key 38 down"
+impl fmt::Display for OutputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4} ms ", self.at.as_millis())?;
+        match self.action {
+            OutputAction::Key { keycode, pressed } => write!(f, "key {} {}", keycode, if pressed { "down" } else { "up" }),
+            OutputAction::Button { button, pressed } => write!(f, "button {:?} {}", button, if pressed { "down" } else { "up" }),
+            OutputAction::Move { dx, dy } => write!(f, "move {} {}", dx, dy),
+            OutputAction::Scroll { steps } => write!(f, "scroll {}", steps),
+            OutputAction::Remap { keycode, keysym: NO_SYMBOL } => write!(f, "remap {} back", keycode),
+            OutputAction::Remap { keycode, keysym } => write!(f, "remap {} to keysym {:#x}", keycode, keysym),
+        }
+    }
+}
+
+// Keeps every event in memory instead of sending it, for tests and traces without a display
+pub struct RecordingSink {
+    start: Instant,
+    events: Mutex<Vec<OutputEvent>>,
+    keymap: Option<Keymap>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        RecordingSink { start: Instant::now(), events: Mutex::new(Vec::new()), keymap: None }
+    }
+
+    // Layout type_text types on, without one type_text fails like on an output without X server
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = Some(keymap);
+        self
+    }
+
+    pub fn events(&self) -> Vec<OutputEvent> {
+        self.events.lock().map(|events| events.clone()).unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut events) = self.events.lock() {
+            events.clear();
+        }
+    }
+
+    // All events, one line each
+    pub fn trace(&self) -> String {
+        self.events().iter().map(|event| format!("{}\n", event)).collect()
+    }
+
+    fn record(&self, action: OutputAction) -> Result<(), KeySimError> {
+        let event = OutputEvent { at: self.start.elapsed(), action };
+        self.events.lock().map_err(|_| "Recording is poisoned")?.push(event);
+        Ok(())
+    }
+}
+
+impl Default for RecordingSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputSink for RecordingSink {
+    fn key(&self, keycode: u8, pressed: bool) -> Result<(), KeySimError> {
+        self.record(OutputAction::Key { keycode, pressed })
+    }
+
+    fn button(&self, button: MouseButton, pressed: bool) -> Result<(), KeySimError> {
+        self.record(OutputAction::Button { button, pressed })
+    }
+
+    fn move_pointer(&self, dx: i32, dy: i32) -> Result<(), KeySimError> {
+        self.record(OutputAction::Move { dx, dy })
+    }
+
+    fn scroll(&self, steps: i32) -> Result<(), KeySimError> {
+        self.record(OutputAction::Scroll { steps })
+    }
+
+    fn keymap(&self) -> Option<Keymap> {
+        self.keymap.clone()
+    }
+
+    fn remap(&self, keycode: u8, keysym: u32) -> Result<(), KeySimError> {
+        self.record(OutputAction::Remap { keycode, keysym })
+    }
+
+    fn can_remap(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,82 @@
+// poller.rs
+// Blocking waits on file descriptors with poll(2), plus an eventfd to wake a waiting thread
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+// eventfd used to interrupt a thread blocked in poll_fds (e.g. to stop a monitor)
+pub struct WakeFd {
+    fd: RawFd,
+}
+
+impl WakeFd {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(WakeFd { fd })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // Makes the fd readable, every poll_fds call that includes it returns
+    pub fn wake(&self) {
+        let value: u64 = 1;
+        unsafe {
+            libc::write(self.fd, &value as *const u64 as *const libc::c_void, std::mem::size_of::<u64>());
+        }
+    }
+
+    // Resets the fd after a wake
+    pub fn clear(&self) {
+        let mut value: u64 = 0;
+        unsafe {
+            libc::read(self.fd, &mut value as *mut u64 as *mut libc::c_void, std::mem::size_of::<u64>());
+        }
+    }
+}
+
+impl Drop for WakeFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// Builds a pollfd that waits for input
+pub fn pollfd(fd: RawFd) -> libc::pollfd {
+    libc::pollfd { fd, events: libc::POLLIN, revents: 0 }
+}
+
+// Whether poll reported input on this fd
+pub fn is_readable(fd: &libc::pollfd) -> bool {
+    fd.revents & libc::POLLIN != 0
+}
+
+// Whether poll reported that this fd is gone (device removed) or broken
+pub fn is_closed(fd: &libc::pollfd) -> bool {
+    fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0
+}
+
+// Blocks until at least one fd is ready or the timeout (None = forever) expires.
+// Returns the number of ready fds, 0 on timeout.
+pub fn poll_fds(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    let timeout_ms = match timeout {
+        Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+
+    loop {
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+        if ready >= 0 {
+            return Ok(ready as usize);
+        }
+
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// recorder.rs
+// Writes the raw events of a monitored device to a file that ReplaySource can play back.
+// Format: see input_source::parse_recording
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::evdev::EV_SYN;
+use crate::input_source::{self, InputEvent};
+
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    // Creates the file and writes the header with the device name
+    pub fn create(path: &Path, device_name: &str) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# MacroKeyB input recording")?;
+        writeln!(writer, "# {}: {}", input_source::DEVICE_HEADER, device_name)?;
+        writeln!(writer, "# <tv_sec>.<tv_usec> <type> <code> <value>")?;
+        writer.flush()?;
+
+        Ok(Recorder { path: path.to_path_buf(), writer })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        writeln!(self.writer, "{}", input_source::format_event(event))?;
+
+        // Flush on every SYN_REPORT, so the file is complete up to the last report if the app crashes
+        if event.type_ == EV_SYN {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::input_source::{parse_recording, ReplaySource};
+
+    fn event(tv_sec: libc::time_t, tv_usec: libc::suseconds_t, type_: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent { tv_sec, tv_usec, type_, code, value }
+    }
+
+    #[test]
+    fn recording_reads_back_unchanged() {
+        let path = std::env::temp_dir().join(format!("macroeng-recorder-{}.txt", std::process::id()));
+        let recorded = [
+            event(1718000000, 5, 1, 30, 1),
+            event(1718000000, 5, 0, 0, 0),
+            event(1718000000, 120000, 1, 30, 2),
+            event(1718000001, 999999, 2, 8, -1),
+            event(1718000002, 0, 1, 30, 0),
+            event(1718000002, 0, 0, 0, 0),
+        ];
+
+        let mut recorder = Recorder::create(&path, "Round Trip Pad").unwrap();
+        for event in &recorded {
+            recorder.write(event).unwrap();
+        }
+        drop(recorder);
+
+        let text = fs::read_to_string(&path);
+        let source = ReplaySource::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(parse_recording(&text.unwrap()).unwrap(), recorded);
+        assert_eq!(source.unwrap().device_name(), Some("Round Trip Pad"));
+    }
+
+    #[test]
+    fn flushes_on_every_report() {
+        let path = std::env::temp_dir().join(format!("macroeng-recorder-flush-{}.txt", std::process::id()));
+
+        let mut recorder = Recorder::create(&path, "Flush Pad").unwrap();
+        recorder.write(&event(1, 0, 1, 30, 1)).unwrap();
+        let before_report = fs::read_to_string(&path).unwrap();
+        recorder.write(&event(1, 0, 0, 0, 0)).unwrap();
+        let after_report = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(parse_recording(&before_report).unwrap().is_empty());
+        assert_eq!(parse_recording(&after_report).unwrap(), [event(1, 0, 1, 30, 1), event(1, 0, 0, 0, 0)]);
+    }
+}
@@ -0,0 +1,254 @@
+// sequence.rs
+// Leader-key sequences: keys tapped one after the other ("G>C") trigger their own item.
+// A tap that starts or continues a bound sequence is held back until the next tap or the timeout.
+// If the sequence is not completed the held back taps are passed on as they were,
+// so a key can have its own item and start a sequence at the same time.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::gesture::{Gesture, GestureInfo};
+use crate::key_codes;
+
+// Whether a bound sequence continues after the typed part (typed part like "G" or "G>C", device name, device id)
+pub type SequenceFilter = Arc<dyn Fn(&str, &str, &str) -> bool + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceStatus {
+    Waiting,    // Typed part of a sequence, waiting for the next key
+    Complete,   // A bound sequence was typed
+    Cancelled,  // A key that does not continue the sequence, or cancel()
+    Timeout,    // No next key within the timeout
+}
+
+// Progress of a sequence, reported on every step
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SequenceStep {
+    pub device: String,
+    pub device_id: String,
+    pub keys: String,  // Typed part like "G>C"
+    pub status: SequenceStatus,
+}
+
+pub enum SequenceEvent {
+    Gesture(GestureInfo),  // Gesture to pass on: a single key or the completed sequence
+    Step(SequenceStep),
+}
+
+#[derive(Default)]
+struct Pending {
+    device: String,  // Device name
+    taps: Vec<GestureInfo>,
+    since: Option<Instant>,  // Last step
+}
+
+pub struct SequenceRecognizer {
+    timeout: Duration,
+    devices: HashMap<String, Pending>,  // Device id -> typed part
+}
+
+impl SequenceRecognizer {
+    pub fn new(timeout: Duration) -> Self {
+        SequenceRecognizer { timeout, devices: HashMap::new() }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // Feeds a recognized gesture. Only taps are part of sequences, any other gesture ends the typed part.
+    // `continues` tells whether a bound sequence continues after a typed part, `is_bound` whether a sequence is bound.
+    pub fn feed(&mut self, info: &GestureInfo, now: Instant, continues: impl Fn(&str) -> bool, is_bound: impl Fn(&str) -> bool) -> Vec<SequenceEvent> {
+        let pending = self.devices.entry(info.device_id.clone()).or_default();
+        pending.device.clone_from(&info.device);
+        let is_tap = info.gesture == Gesture::Tap && key_codes::string_to_key(&info.name).is_some();
+        let mut events = Vec::new();
+
+        if is_tap {
+            let mut keys: Vec<&str> = pending.taps.iter().map(|tap| tap.name.as_str()).collect();
+            keys.push(&info.name);
+            let name = key_codes::sequence_name(&keys);
+
+            if continues(&name) {
+                pending.taps.push(info.clone());
+                pending.since = Some(now);
+                events.push(step(&info.device, &info.device_id, name, SequenceStatus::Waiting));
+                return events;
+            }
+
+            if keys.len() >= 2 && is_bound(&name) {
+                pending.taps.clear();
+                pending.since = None;
+                events.push(step(&info.device, &info.device_id, name.clone(), SequenceStatus::Complete));
+                events.push(SequenceEvent::Gesture(GestureInfo { name, device: info.device.clone(), device_id: info.device_id.clone(), gesture: Gesture::Tap, delta: info.delta }));
+                return events;
+            }
+        }
+
+        if !pending.taps.is_empty() {
+            events.extend(flush(&info.device_id, pending, SequenceStatus::Cancelled, &is_bound));
+
+            // Starts over with this key
+            if is_tap && continues(&info.name) {
+                pending.taps.push(info.clone());
+                pending.since = Some(now);
+                events.push(step(&info.device, &info.device_id, info.name.clone(), SequenceStatus::Waiting));
+                return events;
+            }
+        }
+
+        events.push(SequenceEvent::Gesture(info.clone()));
+        events
+    }
+
+    // Passes on the typed parts that timed out. `is_bound` gets the sequence, device name and device id.
+    pub fn poll(&mut self, now: Instant, is_bound: impl Fn(&str, &str, &str) -> bool) -> Vec<SequenceEvent> {
+        let mut events = Vec::new();
+
+        for (device_id, pending) in self.devices.iter_mut() {
+            if pending.since.is_some_and(|since| now.duration_since(since) >= self.timeout) {
+                let device = pending.device.clone();
+                events.extend(flush(device_id, pending, SequenceStatus::Timeout, &|name: &str| is_bound(name, &device, device_id)));
+            }
+        }
+
+        events
+    }
+
+    // Drops the typed parts of all devices without passing them on
+    pub fn cancel(&mut self) -> Vec<SequenceEvent> {
+        self.devices.iter_mut()
+            .filter(|(_, pending)| !pending.taps.is_empty())
+            .map(|(device_id, pending)| {
+                let taps = std::mem::take(&mut pending.taps);
+                pending.since = None;
+                let keys: Vec<&str> = taps.iter().map(|tap| tap.name.as_str()).collect();
+                step(&pending.device, device_id, key_codes::sequence_name(&keys), SequenceStatus::Cancelled)
+            })
+            .collect()
+    }
+
+    // When poll() has to be called next, None if no sequence is being typed
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.devices.values()
+            .filter_map(|pending| pending.since.map(|since| since + self.timeout))
+            .min()
+    }
+}
+
+// Ends a typed part: runs it if it is a bound sequence itself, otherwise passes its taps on one by one
+fn flush(device_id: &str, pending: &mut Pending, status: SequenceStatus, is_bound: &impl Fn(&str) -> bool) -> Vec<SequenceEvent> {
+    let taps = std::mem::take(&mut pending.taps);
+    pending.since = None;
+    let device = pending.device.as_str();
+
+    let keys: Vec<&str> = taps.iter().map(|tap| tap.name.as_str()).collect();
+    let name = key_codes::sequence_name(&keys);
+
+    if taps.len() >= 2 && is_bound(&name) {
+        let delta = taps.last().map_or(0, |tap| tap.delta);
+        return vec![
+            step(device, device_id, name.clone(), SequenceStatus::Complete),
+            SequenceEvent::Gesture(GestureInfo { name, device: device.to_string(), device_id: device_id.to_string(), gesture: Gesture::Tap, delta }),
+        ];
+    }
+
+    let mut events = vec![step(device, device_id, name, status)];
+    events.extend(taps.into_iter().map(SequenceEvent::Gesture));
+    events
+}
+
+fn step(device: &str, device_id: &str, keys: String, status: SequenceStatus) -> SequenceEvent {
+    SequenceEvent::Step(SequenceStep { device: device.to_string(), device_id: device_id.to_string(), keys, status })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap(name: &str, device_id: &str) -> GestureInfo {
+        GestureInfo { name: name.to_string(), device: "Pad".to_string(), device_id: device_id.to_string(), gesture: Gesture::Tap, delta: 0 }
+    }
+
+    // Gestures as "G", steps as "G>C complete"
+    fn events(events: &[SequenceEvent]) -> Vec<String> {
+        events.iter()
+            .map(|event| match event {
+                SequenceEvent::Gesture(info) => info.name.clone(),
+                SequenceEvent::Step(step) => format!("{} {}", step.keys, serde_json::to_string(&step.status).unwrap().trim_matches('"')),
+            })
+            .collect()
+    }
+
+    // "G>C" is bound, G also has an item of its own
+    fn continues(typed: &str) -> bool {
+        typed == "G"
+    }
+
+    fn is_bound(sequence: &str) -> bool {
+        sequence == "G>C"
+    }
+
+    fn recognizer() -> SequenceRecognizer {
+        SequenceRecognizer::new(Duration::from_millis(1000))
+    }
+
+    #[test]
+    fn completed_sequence_replaces_its_taps() {
+        let mut recognizer = recognizer();
+        let now = Instant::now();
+
+        assert_eq!(events(&recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound)), ["G waiting"]);
+        assert_eq!(events(&recognizer.feed(&tap("C", "pad-1"), now, continues, is_bound)), ["G>C complete", "G>C"]);
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn sequence_times_out() {
+        let mut recognizer = recognizer();
+        let now = Instant::now();
+
+        recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound);
+        assert_eq!(recognizer.next_deadline(), Some(now + Duration::from_millis(1000)));
+        assert!(recognizer.poll(now + Duration::from_millis(999), |_, _, _| false).is_empty());
+        // The held back tap runs its own item
+        assert_eq!(events(&recognizer.poll(now + Duration::from_millis(1000), |_, _, _| false)), ["G timeout", "G"]);
+        assert!(recognizer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn prefix_with_its_own_binding_is_passed_on() {
+        let mut recognizer = recognizer();
+        let now = Instant::now();
+
+        recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound);
+        assert_eq!(events(&recognizer.feed(&tap("X", "pad-1"), now, continues, is_bound)), ["G cancelled", "G", "X"]);
+
+        // G again starts over
+        recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound);
+        assert_eq!(events(&recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound)), ["G cancelled", "G", "G waiting"]);
+    }
+
+    #[test]
+    fn other_gestures_end_the_sequence() {
+        let mut recognizer = recognizer();
+        let now = Instant::now();
+
+        recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound);
+        let hold = GestureInfo { gesture: Gesture::Hold, ..tap("C", "pad-1") };
+        assert_eq!(events(&recognizer.feed(&hold, now, continues, is_bound)), ["G cancelled", "G", "C"]);
+    }
+
+    #[test]
+    fn identical_devices_type_their_own_sequence() {
+        let mut recognizer = recognizer();
+        let now = Instant::now();
+
+        recognizer.feed(&tap("G", "pad-1"), now, continues, is_bound);
+        // C on the other pad does not complete it
+        assert_eq!(events(&recognizer.feed(&tap("C", "pad-2"), now, continues, is_bound)), ["C"]);
+        assert_eq!(events(&recognizer.cancel()), ["G cancelled"]);
+    }
+}
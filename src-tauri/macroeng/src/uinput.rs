@@ -0,0 +1,239 @@
+// uinput.rs
+// Virtual input devices through /dev/uinput, used to pass events of a grabbed device on to the system
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::evdev::{self, ioc, AbsInfo, InputId, IOC_NONE, IOC_WRITE, EV_ABS, EV_KEY, EV_LED, EV_MSC, EV_REL, EV_SYN};
+use crate::input_source::InputEvent;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+// Names of our own devices start with this, so they are never listed or grabbed
+pub const VIRTUAL_DEVICE_PREFIX: &str = "MacroKeyB virtual";
+
+const BUS_VIRTUAL: u16 = 0x06;
+const UINPUT_MAX_NAME_SIZE: usize = 80;
+
+// struct uinput_setup
+#[repr(C)]
+struct UinputSetup {
+    id: InputId,
+    name: [u8; UINPUT_MAX_NAME_SIZE],
+    ff_effects_max: u32,
+}
+
+// struct uinput_abs_setup
+#[repr(C)]
+struct UinputAbsSetup {
+    code: u16,
+    absinfo: AbsInfo,
+}
+
+const UI_DEV_CREATE: u32 = ioc(IOC_NONE, b'U', 1, 0);
+const UI_DEV_DESTROY: u32 = ioc(IOC_NONE, b'U', 2, 0);
+const UI_DEV_SETUP: u32 = ioc(IOC_WRITE, b'U', 3, std::mem::size_of::<UinputSetup>());
+const UI_ABS_SETUP: u32 = ioc(IOC_WRITE, b'U', 4, std::mem::size_of::<UinputAbsSetup>());
+const UI_SET_EVBIT: u32 = ioc(IOC_WRITE, b'U', 100, std::mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u32 = ioc(IOC_WRITE, b'U', 101, std::mem::size_of::<libc::c_int>());
+const UI_SET_RELBIT: u32 = ioc(IOC_WRITE, b'U', 102, std::mem::size_of::<libc::c_int>());
+const UI_SET_MSCBIT: u32 = ioc(IOC_WRITE, b'U', 104, std::mem::size_of::<libc::c_int>());
+const UI_SET_LEDBIT: u32 = ioc(IOC_WRITE, b'U', 105, std::mem::size_of::<libc::c_int>());
+
+const SYN_REPORT: u16 = 0;
+
+pub struct VirtualDevice {
+    file: File,
+}
+
+impl VirtualDevice {
+    // Virtual device with the same keys, relative and absolute axes, scan codes and LEDs as the device behind `fd`
+    pub fn mirror(fd: RawFd, name: &str) -> io::Result<Self> {
+        let types = evdev::read_bits(fd, 0, evdev::EV_MAX)?;
+
+        let mut keys = Vec::new();
+        if types.has(EV_KEY) {
+            let bits = evdev::read_bits(fd, EV_KEY, evdev::KEY_MAX)?;
+            keys.extend((0..=evdev::KEY_MAX).filter(|&code| bits.has(code)));
+        }
+
+        let mut rels = Vec::new();
+        if types.has(EV_REL) {
+            let bits = evdev::read_bits(fd, EV_REL, evdev::REL_MAX)?;
+            rels.extend((0..=evdev::REL_MAX).filter(|&code| bits.has(code)));
+        }
+
+        let mut axes = Vec::new();
+        if types.has(EV_ABS) {
+            let bits = evdev::read_bits(fd, EV_ABS, evdev::ABS_MAX)?;
+            for code in (0..=evdev::ABS_MAX).filter(|&code| bits.has(code)) {
+                axes.push((code, evdev::read_abs_info(fd, code)?));
+            }
+        }
+
+        let mut mscs = Vec::new();
+        if types.has(EV_MSC) {
+            let bits = evdev::read_bits(fd, EV_MSC, evdev::MSC_MAX)?;
+            mscs.extend((0..=evdev::MSC_MAX).filter(|&code| bits.has(code)));
+        }
+
+        let mut leds = Vec::new();
+        if types.has(EV_LED) {
+            let bits = evdev::read_bits(fd, EV_LED, evdev::LED_MAX)?;
+            leds.extend((0..=evdev::LED_MAX).filter(|&code| bits.has(code)));
+        }
+
+        Self::create(name, &keys, &rels, &axes, &mscs, &leds)
+    }
+
+    pub fn create(name: &str, keys: &[u16], rels: &[u16], axes: &[(u16, AbsInfo)], mscs: &[u16], leds: &[u16]) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        if !keys.is_empty() {
+            set_bit(fd, UI_SET_EVBIT, EV_KEY)?;
+            for &key in keys {
+                set_bit(fd, UI_SET_KEYBIT, key)?;
+            }
+        }
+
+        if !rels.is_empty() {
+            set_bit(fd, UI_SET_EVBIT, EV_REL)?;
+            for &rel in rels {
+                set_bit(fd, UI_SET_RELBIT, rel)?;
+            }
+        }
+
+        if !axes.is_empty() {
+            set_bit(fd, UI_SET_EVBIT, EV_ABS)?;
+            for &(code, absinfo) in axes {
+                let setup = UinputAbsSetup { code, absinfo };
+                if unsafe { libc::ioctl(fd, UI_ABS_SETUP as _, &setup as *const UinputAbsSetup) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        if !mscs.is_empty() {
+            set_bit(fd, UI_SET_EVBIT, EV_MSC)?;
+            for &msc in mscs {
+                set_bit(fd, UI_SET_MSCBIT, msc)?;
+            }
+        }
+
+        if !leds.is_empty() {
+            set_bit(fd, UI_SET_EVBIT, EV_LED)?;
+            for &led in leds {
+                set_bit(fd, UI_SET_LEDBIT, led)?;
+            }
+        }
+
+        let mut setup = UinputSetup {
+            id: InputId { bustype: BUS_VIRTUAL, vendor: 0, product: 0, version: 1 },
+            name: [0; UINPUT_MAX_NAME_SIZE],
+            ff_effects_max: 0,
+        };
+        let full_name = format!("{} {}", VIRTUAL_DEVICE_PREFIX, name);
+        let name_len = full_name.len().min(UINPUT_MAX_NAME_SIZE - 1);
+        setup.name[..name_len].copy_from_slice(&full_name.as_bytes()[..name_len]);
+
+        unsafe {
+            if libc::ioctl(fd, UI_DEV_SETUP as _, &setup as *const UinputSetup) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ioctl(fd, UI_DEV_CREATE as _) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(VirtualDevice { file })
+    }
+
+    // Sends one event, the kernel sets the timestamp
+    pub fn emit(&mut self, type_: u16, code: u16, value: i32) -> io::Result<()> {
+        let event = InputEvent::new(type_, code, value);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&event as *const InputEvent as *const u8, std::mem::size_of::<InputEvent>())
+        };
+        self.file.write_all(bytes)
+    }
+
+    // Ends a group of events (SYN_REPORT), applications see them only after this
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        unsafe { libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY as _) };
+    }
+}
+
+fn set_bit(fd: RawFd, request: u32, code: u16) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, request as _, code as libc::c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::path::PathBuf;
+
+    const KEY_A: u16 = 30;
+    const LED_CAPSL: u16 = 0x01;
+
+    // Event node of a virtual device, e.g. /dev/input/event7 (UI_GET_SYSNAME gives "input7", its event* entry in sysfs the node)
+    fn event_node(device: &VirtualDevice) -> io::Result<PathBuf> {
+        let mut sysname = [0u8; 64];
+        let request = ioc(evdev::IOC_READ, b'U', 44, sysname.len());
+        if unsafe { libc::ioctl(device.file.as_raw_fd(), request as _, sysname.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sysname = CStr::from_bytes_until_nul(&sysname)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .to_string_lossy()
+            .into_owned();
+
+        std::fs::read_dir(PathBuf::from("/sys/class/input").join(sysname))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .find(|name| name.starts_with("event"))
+            .map(|name| PathBuf::from("/dev/input").join(name))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no event node"))
+    }
+
+    // EVIOCGLED: current state of the LEDs
+    fn led_is_on(fd: RawFd, led: u16) -> bool {
+        let mut state = [0u8; evdev::LED_MAX as usize / 8 + 1];
+        let request = ioc(evdev::IOC_READ, b'E', 0x19, state.len());
+        assert!(unsafe { libc::ioctl(fd, request as _, state.as_mut_ptr()) } >= 0, "{}", io::Error::last_os_error());
+        state[led as usize / 8] & (1 << (led % 8)) != 0
+    }
+
+    #[test]
+    fn switches_an_led_of_a_virtual_keyboard() {
+        let device = match VirtualDevice::create("LED test", &[KEY_A], &[], &[], &[], &[LED_CAPSL]) {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("Skipped, no uinput keyboard: {}", e);
+                return;
+            },
+        };
+        let node = event_node(&device).unwrap();
+        let file = OpenOptions::new().read(true).write(true).open(&node).unwrap();
+
+        evdev::write_led(file.as_raw_fd(), LED_CAPSL, true).unwrap();
+        assert!(led_is_on(file.as_raw_fd(), LED_CAPSL));
+
+        evdev::write_led(file.as_raw_fd(), LED_CAPSL, false).unwrap();
+        assert!(!led_is_on(file.as_raw_fd(), LED_CAPSL));
+    }
+}
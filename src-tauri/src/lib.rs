@@ -4,9 +4,10 @@ use serde::{Deserialize, Serialize};
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use tauri::{window, Emitter, Manager};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
-use std::sync::{Mutex};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::{SystemTime};
@@ -14,8 +15,9 @@ use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use macroeng::KeyboardListener;
 use macroeng::LuaManager;
+use macroeng::{Gesture, GestureTimings, Gestures};
+use macroeng::OutputBackend;
 use tokio::task;
-use tokio::time;
 
 
 mod frontend;
@@ -23,14 +25,44 @@ mod frontend;
 // AppState struct to manage the application's global state using thread-safe primitives
 struct AppState {
     blocking_active: AtomicBool,          // Flag indicating if keyboard blocking is active
+    device_access: AtomicBool,            // Input devices can be read (root), without it detection and blocking are off
     detection_in_progress: AtomicBool,    // Flag indicating if keyboard detection is in progress
-    cancel_detection: AtomicBool,         // Flag to signal cancellation of detection
-    blocking_thread: Mutex<Option<thread::JoinHandle<()>>>, // Handle to the blocking thread
-    current_device: Mutex<Option<String>>, // Currently blocked device name
+    cancel_detection: Mutex<Option<KeyboardListener::CancelToken>>, // Cancels the running detection
+    monitors: Mutex<Vec<KeyboardListener::Monitor>>, // Handles of the running monitors, one per blocked device
+    current_devices: Mutex<Vec<String>>,  // Names of the currently blocked devices
     items: Mutex<Option<Vec<Item>>>,      // Collection of macro items
     app_data_dir: Mutex<Option<PathBuf>>, // Application data directory path
     assign_mode_active: AtomicBool,   
     item_waiting_for_key: Mutex<Option<String>>,
+    assign_any_device: AtomicBool,        // Assign the next key without binding it to the device it came from
+    auto_regrab: AtomicBool,              // Block saved devices again when they are plugged back in
+    macro_running_led: Mutex<Option<u16>>, // LED that is on while a macro runs
+    running_macros: Mutex<usize>,         // Number of scripts running right now, the LED goes off when the last one ends
+    layer_leds: Mutex<Vec<(String, u16)>>, // Layer and the LED that is on while it is active
+    held_keys: Mutex<Vec<HeldKey>>,       // Keys that are down, for auto repeat and key-up scripts
+    layers: Mutex<Layers>,                // Toggled layer and held momentary layer keys
+    script_output: Mutex<Option<Arc<dyn macroeng::OutputSink>>>, // Sink for the scripts instead of the output backend, e.g. a RecordingSink in tests
+}
+
+// A key that is held down and the item its press ran
+struct HeldKey {
+    key: String,
+    device_id: String,
+    item: Option<(String, String, Gesture)>, // Item ID, trigger ("A", its chord "A+S" or sequence "G>A") and gesture, None until a gesture was recognized
+}
+
+// Layers the items are resolved against.
+// A held momentary layer key wins over the toggled layer, the last one pressed if several are held.
+struct Layers {
+    toggled: Option<String>,
+    held: Vec<(String, String)>, // Item ID of a held momentary layer key and its layer
+}
+
+impl Layers {
+    // Active layer, None = base layer
+    fn active(&self) -> Option<&str> {
+        self.held.last().map(|(_, layer)| layer.as_str()).or(self.toggled.as_deref())
+    }
 }
 
 // Implementation of AppState with a const constructor for static initialization
@@ -38,14 +70,23 @@ impl AppState {
     const fn new() -> Self {
         Self {
             blocking_active: AtomicBool::new(false),
+            device_access: AtomicBool::new(false),
             detection_in_progress: AtomicBool::new(false),
-            cancel_detection: AtomicBool::new(false),
-            blocking_thread: Mutex::new(None),
-            current_device: Mutex::new(None),
+            cancel_detection: Mutex::new(None),
+            monitors: Mutex::new(Vec::new()),
+            current_devices: Mutex::new(Vec::new()),
             items: Mutex::new(None),
             app_data_dir: Mutex::new(None),
                      assign_mode_active: AtomicBool::new(false),
             item_waiting_for_key: Mutex::new(None),
+            assign_any_device: AtomicBool::new(false),
+            auto_regrab: AtomicBool::new(false),
+            macro_running_led: Mutex::new(None),
+            running_macros: Mutex::new(0),
+            layer_leds: Mutex::new(Vec::new()),
+            held_keys: Mutex::new(Vec::new()),
+            layers: Mutex::new(Layers { toggled: None, held: Vec::new() }),
+            script_output: Mutex::new(None),
 
         }
     }
@@ -55,15 +96,94 @@ impl AppState {
 static STATE: AppState = AppState::new();
 
 // Definition of an Item representing a keyboard macro
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct Item {
     display_text: String,  // Display name of the macro
     assigned_key: String,  // Key that triggers the macro
+    #[serde(default)]
+    assigned_device: Option<String>, // Device the key belongs to, None = any device
+    #[serde(default)]
+    assigned_device_id: Option<String>, // Stable id of that device (DeviceInfo::device_id), None = matched by name (older items)
+    #[serde(default)]
+    assigned_gesture: Gesture, // Tap, double tap or hold of the key
     id: String,            // Unique identifier for the macro
     content: String,       // Lua script content to execute
+    #[serde(default)]
+    key_up_content: Option<String>, // Lua script run when the key is released, e.g. to release a pressed modifier
+    #[serde(default)]
+    repeat: bool,          // Run `content` again on every auto repeat while the key is held
+    #[serde(default)]
+    layer: Option<String>, // Layer the key binding belongs to, None = base layer
+    #[serde(default)]
+    layer_switch: Option<LayerSwitch>, // Makes the key a layer key, it switches the layer instead of running `content`
     is_selected: bool,     // Flag indicating if the item is selected in UI
 }
 
+impl Item {
+    fn has_device(&self) -> bool {
+        self.assigned_device.is_some() || self.assigned_device_id.is_some()
+    }
+    
+    // Whether the key binding is for this device: by the id, so identical devices are kept apart (see
+    // DeviceInfo::is_bound_to), by name for items assigned before there was an id, every device without a device
+    fn is_for_device(&self, device_name: &str, device_id: &str) -> bool {
+        match (&self.assigned_device_id, &self.assigned_device) {
+            (Some(id), _) => KeyboardListener::Instance::new().is_bound_device(id, device_id),
+            (None, Some(name)) => name == device_name,
+            (None, None) => true,
+        }
+    }
+}
+
+// Layer key of an item
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct LayerSwitch {
+    layer: String,
+    mode: LayerMode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LayerMode {
+    Momentary, // Layer is active while the key is held
+    Toggle,    // Each press switches the layer on or off
+}
+
+// Application settings stored next to items.json
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct Settings {
+    #[serde(default)]
+    key_names_version: u32, // Key names version of items.json files saved as a bare list, it is stored in items.json now
+    #[serde(default)]
+    selective_grab: bool,   // Only swallow keys bound to items, pass the others on to the system
+    #[serde(default)]
+    macro_running_led: Option<String>, // LED of the blocked devices that is on while a macro runs ("scroll", ...)
+    #[serde(default)]
+    layer_leds: HashMap<String, String>, // Layer and the LED that shows it is active ("media" -> "caps", ...)
+    #[serde(default)]
+    gesture_timings: GestureTimings, // Hold and double tap thresholds
+    #[serde(default)]
+    output_backend: OutputBackend, // How macros send keys: auto, xtest or uinput
+}
+
+// Current version of the key names, increase when key names change and add a migration
+const KEY_NAMES_VERSION: u32 = 1;
+
+// Layout of items.json: the key names version is saved together with the items it describes,
+// so a migration never runs twice on the same data. Older versions saved a bare list of items.
+#[derive(Serialize, Deserialize)]
+struct ItemsFile {
+    key_names_version: u32,
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredItems {
+    File(ItemsFile),
+    List(Vec<Item>),
+}
+
 
 // ====== File Operation Functions ======
 // Initialize the configuration directory
@@ -110,27 +230,72 @@ fn get_keyb_path() -> PathBuf {
         })
 }
 
+// Get path to the settings JSON file
+fn get_settings_path() -> PathBuf {
+    STATE.app_data_dir.lock().unwrap()
+        .as_ref()
+        .map(|path| path.join("settings.json"))
+        .unwrap_or_else(|| {
+            // Fallback path if app_data_dir is not set
+            std::env::current_dir()
+                .unwrap_or_else(|_| PathBuf::from("/home/a7"))
+                .join("settings.json")
+        })
+}
+
+// Load settings from the JSON file, defaults if missing or invalid
+fn load_settings() -> Settings {
+    let path = get_settings_path();
+
+    if !path.exists() {
+        return Settings::default();
+    }
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Error deserializing settings: {}", e);
+            Settings::default()
+        }),
+        Err(e) => {
+            eprintln!("Error reading the settings file: {}", e);
+            Settings::default()
+        }
+    }
+}
+
+// Save settings to the JSON file
+fn save_settings_to_file(settings: &Settings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Error serializing settings: {}", e))?;
+
+    fs::write(get_settings_path(), json)
+        .map_err(|e| format!("Error writing settings file: {}", e))
+}
+
 // Load items from the JSON file
-fn load_items() -> Vec<Item> {
+fn load_items() -> ItemsFile {
     let path = get_items_path();
+    let empty = ItemsFile { key_names_version: KEY_NAMES_VERSION, items: Vec::new() };
     
     // Return empty vector if file doesn't exist
     if !path.exists() {
-        return Vec::new();
+        return empty;
     }
     
     // Read and deserialize file contents, handling errors
     match fs::read_to_string(&path) {
         Ok(contents) => match serde_json::from_str(&contents) {
-            Ok(items) => items,
+            Ok(StoredItems::File(file)) => file,
+            // A bare list was saved before the version moved into items.json, it was kept in settings.json
+            Ok(StoredItems::List(items)) => ItemsFile { key_names_version: load_settings().key_names_version, items },
             Err(e) => {
                 eprintln!("Error deserializing items: {}", e);
-                Vec::new()
+                empty
             }
         },
         Err(e) => {
             eprintln!("Error reading the JSON file: {}", e);
-            Vec::new()
+            empty
         }
     }
 }
@@ -149,8 +314,9 @@ fn save_items_to_file(items: &[Item]) -> Result<(), String> {
         }
     }
     
-    // Serialize items to JSON
-    let json = match serde_json::to_string_pretty(items) {
+    // Serialize items to JSON, always with the current key names
+    let file = ItemsFile { key_names_version: KEY_NAMES_VERSION, items: items.to_vec() };
+    let json = match serde_json::to_string_pretty(&file) {
         Ok(json) => json,
         Err(e) => return Err(format!("Error serializing items: {}", e)),
     };
@@ -176,7 +342,36 @@ fn save_items_to_file(items: &[Item]) -> Result<(), String> {
 fn init_items() {
     let mut items_lock = STATE.items.lock().unwrap();
     if items_lock.is_none() {
-        *items_lock = Some(load_items());
+        let mut file = load_items();
+        migrate_key_names(&mut file);
+        *items_lock = Some(file.items);
+    }
+}
+
+// One-time migration of assigned keys saved with older key names.
+// The legacy names chain ("10" -> "0" -> "-"), so it must only run on items saved with an older version.
+fn migrate_key_names(file: &mut ItemsFile) {
+    if file.key_names_version >= KEY_NAMES_VERSION {
+        return;
+    }
+
+    for item in file.items.iter_mut() {
+        if macroeng::key_codes::AMBIGUOUS_LEGACY_NAMES.contains(&item.assigned_key.as_str()) {
+            println!("Note: '{}' is bound to '{}', which used to match the left/main and the right/keypad key. Reassign the key if you used the right/keypad one.",
+                item.display_text, item.assigned_key);
+        }
+
+        if let Some(new_key) = macroeng::key_codes::migrate_legacy_name(&item.assigned_key) {
+            println!("Migrating key of '{}': {} -> {}", item.display_text, item.assigned_key, new_key);
+            item.assigned_key = new_key;
+        }
+    }
+
+    // Items and version are written together. If that fails the file keeps the old names and the old version,
+    // the migration runs again on the unchanged data next time.
+    match save_items_to_file(&file.items) {
+        Ok(()) => file.key_names_version = KEY_NAMES_VERSION,
+        Err(e) => eprintln!("Error saving migrated items: {}", e),
     }
 }
 
@@ -186,14 +381,167 @@ fn add_standard_key_listener(keyb: &KeyboardListener::Instance) {
     // Clear previous listeners
     keyb.on_key.clear_listeners();
     
-    // Add new listener to process key events
+    // Add new listener to process key events, the gesture recognizer decides which item runs
     keyb.on_key.add_listener(|info| {
         match info.state {
-            KeyboardListener::KeyState::Down => {println!("Key down: {} {}", info.name, info.state); handle_key(&info.name, &info.state)}, 
+            KeyboardListener::KeyState::Down => println!("Key down: {} {} ({})", info.name, info.state, info.device),
             KeyboardListener::KeyState::Press => (),
-            KeyboardListener::KeyState::Up => {println!("Key up: {} {}", info.name, info.state); /*handle_key(&info.name, &info.state);*/}, 
+            KeyboardListener::KeyState::Up => println!("Key up: {} {} ({})", info.name, info.state, info.device),
+        }
+        handle_key(&info.name, &info.state, &info.device, &info.device_id, info.delta);
+        Gestures::new().feed(info);
+    });
+    
+    let gestures = Gestures::new();
+    gestures.on_gesture.clear_listeners();
+    gestures.on_gesture.add_listener(|info| {
+        println!("Gesture: {} {} ({})", info.name, info.gesture, info.device);
+        handle_gesture(&info.name, info.gesture, &info.device, &info.device_id, info.delta);
+    });
+    
+    // Let the UI show "waiting for next key" while a sequence is typed
+    gestures.on_sequence.clear_listeners();
+    gestures.on_sequence.add_listener(|step| {
+        println!("Sequence: {} {:?} ({})", step.keys, step.status, step.device);
+        let payload = serde_json::json!({
+            "keys": step.keys,
+            "device": step.device,
+            "status": step.status,
+            "timestamp": get_formatted_timestamp(),
+        });
+        if let Err(e) = frontend::send_event("sequence-step", &payload.to_string()) {
+            eprintln!("Error sending Sequence-Step event: {}", e);
+        }
+    });
+}
+
+// Only wait for a double tap or hold if an item is bound to it, otherwise a tap runs on key down
+fn apply_gesture_filter() {
+    init_items();
+    Gestures::new().set_filter(Some(Arc::new(|key: &str, device: &str, device_id: &str, gesture: Gesture| {
+        // The key being assigned can be any gesture
+        if STATE.assign_mode_active.load(Ordering::SeqCst) {
+            return true;
+        }
+        match &*STATE.items.lock().unwrap() {
+            Some(items) => find_item_for_key(items, key, device, device_id, gesture, active_layer().as_deref()).is_some(),
+            None => false,
+        }
+    })));
+    
+    // Keys of a bound chord are held back until it is clear whether they are pressed alone
+    Gestures::new().set_chord_filter(Some(Arc::new(|key: &str, device: &str, device_id: &str| {
+        // While assigning, every key can start a chord
+        if STATE.assign_mode_active.load(Ordering::SeqCst) {
+            return true;
+        }
+        match &*STATE.items.lock().unwrap() {
+            Some(items) => is_chord_member(items, key, device, device_id),
+            None => false,
+        }
+    })));
+    
+    // Taps that start or continue a bound sequence ("G>C") wait for the next key
+    Gestures::new().set_sequence_filter(Some(Arc::new(|typed: &str, device: &str, device_id: &str| {
+        // While assigning, taps are collected until the sequence times out
+        if STATE.assign_mode_active.load(Ordering::SeqCst) {
+            return true;
+        }
+        match &*STATE.items.lock().unwrap() {
+            Some(items) => continues_sequence(items, typed, device, device_id, active_layer().as_deref()),
+            None => false,
+        }
+    })));
+}
+
+// Whether a sequence bound on the active or base layer continues after the typed part ("G" or "G>C")
+fn continues_sequence(items: &[Item], typed: &str, device_name: &str, device_id: &str, layer: Option<&str>) -> bool {
+    let prefix = format!("{}{}", typed, macroeng::key_codes::SEQUENCE_SEPARATOR);
+    items.iter()
+        .filter(|item| item.layer.is_none() || item.layer.as_deref() == layer)
+        .filter(|item| item.is_for_device(device_name, device_id))
+        .any(|item| item.assigned_key.starts_with(&prefix) && macroeng::key_codes::sequence_keys(&item.assigned_key).is_some())
+}
+
+// Whether the key has an item for this device on any layer.
+// Not only the active layer: a layer switch while the key is down must not pass on just its release.
+// Keys of sequences count as bound too.
+fn is_key_bound(items: &[Item], key_name: &str, device_name: &str, device_id: &str) -> bool {
+    items.iter()
+        .filter(|item| item.is_for_device(device_name, device_id))
+        .any(|item| item.assigned_key == key_name
+            || macroeng::key_codes::sequence_keys(&item.assigned_key).is_some_and(|keys| keys.contains(&key_name)))
+}
+
+// Whether the key is part of a chord ("A+S") that is bound for this device
+fn is_chord_member(items: &[Item], key_name: &str, device_name: &str, device_id: &str) -> bool {
+    items.iter()
+        .filter(|item| item.is_for_device(device_name, device_id))
+        .filter_map(|item| macroeng::key_codes::chord_keys(&item.assigned_key))
+        .any(|keys| keys.contains(&key_name))
+}
+
+// Report plugged in / removed devices to the frontend and block saved devices again when they come back
+fn add_hotplug_listener(keyb: &'static KeyboardListener::Instance) {
+    keyb.on_device.clear_listeners();
+    keyb.on_monitor_end.clear_listeners();
+    
+    // A blocked device was unplugged
+    keyb.on_monitor_end.add_listener(|_device| update_blocked_devices());
+    
+    keyb.on_device.add_listener(|change| {
+        match change {
+            KeyboardListener::DeviceChange::Connected(device) => {
+                let payload = serde_json::json!({
+                    "name": device.device_name,
+                    "path": device.device_path,
+                    "kind": device.kind(),
+                    "timestamp": get_formatted_timestamp(),
+                });
+                if let Err(e) = frontend::send_event("device-connected", &payload.to_string()) {
+                    eprintln!("Error sending Device-Connected event: {}", e);
+                }
+                
+                if STATE.auto_regrab.load(Ordering::SeqCst) && !STATE.detection_in_progress.load(Ordering::SeqCst) {
+                    regrab_saved_device(device);
+                }
+            },
+            KeyboardListener::DeviceChange::Disconnected { device_path, device_name } => {
+                let payload = serde_json::json!({
+                    "name": device_name,
+                    "path": device_path,
+                    "timestamp": get_formatted_timestamp(),
+                });
+                if let Err(e) = frontend::send_event("device-disconnected", &payload.to_string()) {
+                    eprintln!("Error sending Device-Disconnected event: {}", e);
+                }
+            },
         }
     });
+    
+    keyb.start_hotplug_monitor();
+}
+
+// Block a plugged in device if it is one of the saved devices
+fn regrab_saved_device(device: &KeyboardListener::DeviceInfo) {
+    let keyb = KeyboardListener::Instance::new();
+    let keyb_path = get_keyb_path();
+    let saved_devices = match keyb.load_devices_from_json(keyb_path.to_str().unwrap_or("/home/a7/keyb.json")) {
+        Ok(devices) => devices,
+        Err(_) => return,
+    };
+    
+    let is_saved = keyb.resolve_saved_devices(&saved_devices)
+        .into_iter()
+        .flatten()
+        .any(|dev| dev.device_path == device.device_path);
+    
+    if is_saved && !keyb.is_monitoring(&device.device_path) {
+        println!("Saved keyboard reconnected: {}", device.device_name);
+        if let Err(e) = start_blocking_device(device) {
+            eprintln!("Error blocking reconnected device: {}", e);
+        }
+    }
 }
 
 // Imports for formatted timestamps
@@ -206,168 +554,419 @@ fn get_formatted_timestamp() -> String {
     datetime.format("Date: %Y-%m-%d, Time: %H:%M:%S%.3fZ").to_string()
 }
 
-fn handle_key(key_name: &str, key_state: &KeyboardListener::KeyState) {
-    // Only react to Key-Down events
-    if let KeyboardListener::KeyState::Down = key_state {
-        println!("Processing key down: {}", key_name);
-        
-        // Check if we are in assignment mode
-        if STATE.assign_mode_active.load(Ordering::SeqCst) {
-            // Process key assignment
-            let item_id = {
-                let mut item_id_guard = STATE.item_waiting_for_key.lock().unwrap();
-                item_id_guard.take() // Take ID and set Option to None
-            };
+// Key down, auto repeat and key up of a monitored key.
+// The item of a key runs once its gesture is recognized (handle_gesture), its key-up script on release
+// and, if the item repeats, `content` again on every auto repeat.
+// delta: steps of a wheel or knob trigger, 0 for keys
+fn handle_key(key_name: &str, key_state: &KeyboardListener::KeyState, device_name: &str, device_id: &str, delta: i32) {
+    match key_state {
+        KeyboardListener::KeyState::Down => {
+            let mut held_keys = STATE.held_keys.lock().unwrap();
+            held_keys.retain(|held| held.key != key_name || held.device_id != device_id);
+            held_keys.push(HeldKey { key: key_name.to_string(), device_id: device_id.to_string(), item: None });
+        },
+        KeyboardListener::KeyState::Press => {
+            let held_item = STATE.held_keys.lock().unwrap().iter()
+                .find(|held| held.key == key_name && held.device_id == device_id)
+                .and_then(|held| held.item.clone());
             
-            if let Some(id) = item_id {
-                // Assign the key to the item
-                match assign_key_to_item(&id, key_name.to_string()) {
-                    Ok(_) => {
-                        let timestamp = get_formatted_timestamp();
-                        println!("Key '{}' was assigned to item with ID '{}'", key_name, id);
-                        // also send an event with a timestamp here if needed
-                        if let Err(e) = frontend::send_event("key-assigned", &format!("{{\"status\":\"success\",\"itemId\":\"{}\",\"key\":\"{}\",\"timestamp\":\"{}\"}}", id, key_name, timestamp)) {
-                            eprintln!("Error sending Key-Assigned event: {}", e);
-                        }
-                    },
-                    Err(e) => {
-                        println!("Error during assignment: {}", e);
-                        // Reset item ID so the UI can display the error
-                        *STATE.item_waiting_for_key.lock().unwrap() = Some(format!("ERROR:{}", e));
-                        
-                        // Send error message with timestamp
-                        let timestamp = get_formatted_timestamp();
-                        if let Err(send_err) = frontend::send_event("key-assign-error", &format!("{{\"status\":\"error\",\"itemId\":\"{}\",\"key\":\"{}\",\"error\":\"{}\",\"timestamp\":\"{}\"}}",
-                            id, 
-                            key_name, 
-                            e.to_string().replace("\"", "\\\"").replace("\n", "\\n"),
-                            timestamp)) {
-                            eprintln!("Fehler beim Senden des Key-Assign-Error-Events: {}", send_err);
-                        }
-                    }
-                }
-                
-                // Zuweisungsmodus beenden
-                STATE.assign_mode_active.store(false, Ordering::SeqCst);
-            } else {
-                // Should not happen - we are in assignment mode without item ID
-                eprintln!("Assignment mode active, but no item ID available");
-                STATE.assign_mode_active.store(false, Ordering::SeqCst);
-                
-                // Send error message with timestamp
-                let timestamp = get_formatted_timestamp();
-                if let Err(e) = frontend::send_event("assign-mode-error", &format!("{{\"status\":\"error\",\"message\":\"Assignment mode active without item ID\",\"timestamp\":\"{}\"}}", timestamp)) {
-                    eprintln!("Error sending Assign-Mode-Error event: {}", e);
+            if let Some((item_id, trigger, gesture)) = held_item {
+                if let Some(item) = get_item_by_id(&item_id).filter(|item| item.repeat && item.layer_switch.is_none()) {
+                    run_item_script(&item.id, &item.display_text, &item.content, &trigger, device_name, delta, gesture);
                 }
             }
+        },
+        KeyboardListener::KeyState::Up => {
+            let held_item = {
+                let mut held_keys = STATE.held_keys.lock().unwrap();
+                let index = held_keys.iter().position(|held| held.key == key_name && held.device_id == device_id);
+                let item = index.and_then(|index| held_keys.remove(index).item);
+                
+                // The keys of a chord release its item with the last of them
+                item.filter(|(item_id, _, _)| !held_keys.iter()
+                    .any(|held| held.device_id == device_id && held.item.as_ref().is_some_and(|(id, _, _)| id == item_id)))
+            };
             
-            return; // No further processing in assignment mode
+            if let Some((item_id, trigger, gesture)) = held_item {
+                release_item(&item_id, &trigger, device_name, delta, gesture);
+            }
+        },
+    }
+}
+
+// The key of an item was released: ends its momentary layer and runs its key-up script
+fn release_item(item_id: &str, key_name: &str, device_name: &str, delta: i32, gesture: Gesture) {
+    change_layers(|layers| layers.held.retain(|(id, _)| id != item_id));
+    run_key_up_script(item_id, key_name, device_name, delta, gesture);
+}
+
+// Runs the key-up script of an item, if it has one
+fn run_key_up_script(item_id: &str, key_name: &str, device_name: &str, delta: i32, gesture: Gesture) {
+    if let Some(item) = get_item_by_id(item_id) {
+        if let Some(content) = item.key_up_content.as_deref().filter(|content| !content.trim().is_empty()) {
+            run_item_script(&item.id, &item.display_text, content, key_name, device_name, delta, gesture);
         }
+    }
+}
+
+fn get_item_by_id(item_id: &str) -> Option<Item> {
+    STATE.items.lock().unwrap().as_ref()?
+        .iter()
+        .find(|item| item.id == item_id)
+        .cloned()
+}
+
+// Runs the item bound to a recognized gesture of a key, or assigns the key in assignment mode.
+// gesture: tap, double tap or hold of the key
+// delta: steps of a wheel or knob trigger, 0 for keys
+fn handle_gesture(key_name: &str, gesture: Gesture, device_name: &str, device_id: &str, delta: i32) {
+    println!("Processing {} of {} from {}", gesture, key_name, device_name);
+    
+    // Check if we are in assignment mode
+    if STATE.assign_mode_active.load(Ordering::SeqCst) {
+        // Process key assignment
+        let item_id = {
+            let mut item_id_guard = STATE.item_waiting_for_key.lock().unwrap();
+            item_id_guard.take() // Take ID and set Option to None
+        };
         
-        // Normal processing when not in assignment mode
-        let script_content = {
-            let items_guard = STATE.items.lock().unwrap();
-            let items = match &*items_guard {
-                Some(items) => items,
-                None => {
-                    println!("No items available");
+        if let Some(id) = item_id {
+            // Bind to the source device unless "any device" was requested
+            let (device, bound_device_id) = if STATE.assign_any_device.load(Ordering::SeqCst) {
+                (None, None)
+            } else {
+                (Some(device_name.to_string()), Some(device_id.to_string()))
+            };
+            
+            // Assign the key to the item
+            match assign_key_to_item(&id, key_name.to_string(), device.clone(), bound_device_id, gesture) {
+                Ok(_) => {
+                    println!("Key '{}' ({}, {}) was assigned to item with ID '{}'", key_name, gesture, device.as_deref().unwrap_or("any device"), id);
+                    // also send an event with a timestamp here if needed
+                    let payload = serde_json::json!({
+                        "status": "success",
+                        "itemId": id,
+                        "key": key_name,
+                        "gesture": gesture.to_string(),
+                        "device": device,
+                        "timestamp": get_formatted_timestamp(),
+                    });
+                    if let Err(e) = frontend::send_event("key-assigned", &payload.to_string()) {
+                        eprintln!("Error sending Key-Assigned event: {}", e);
+                    }
+                },
+                Err(e) => {
+                    println!("Error during assignment: {}", e);
+                    // Reset item ID so the UI can display the error
+                    *STATE.item_waiting_for_key.lock().unwrap() = Some(format!("ERROR:{}", e));
                     
                     // Send error message with timestamp
-                    let timestamp = get_formatted_timestamp();
-                    if let Err(e) = frontend::send_event("items-error", &format!("{{\"status\":\"error\",\"message\":\"No items available\",\"timestamp\":\"{}\"}}", timestamp)) {
-                        eprintln!("Error sending Items-Error event: {}", e);
+                    let payload = serde_json::json!({
+                        "status": "error",
+                        "itemId": id,
+                        "key": key_name,
+                        "error": e,
+                        "timestamp": get_formatted_timestamp(),
+                    });
+                    if let Err(send_err) = frontend::send_event("key-assign-error", &payload.to_string()) {
+                        eprintln!("Fehler beim Senden des Key-Assign-Error-Events: {}", send_err);
                     }
-                    return;
                 }
-            };
+            }
             
-            if let Some(item) = items.iter().find(|item| item.assigned_key == key_name) {
-                println!("Found matching item: {} with key {}", item.display_text, item.assigned_key);
-                Some((item.id.clone(), item.display_text.clone(), item.content.clone()))
-            } else {
-                println!("No item found with key: {}", key_name);
+            // Zuweisungsmodus beenden
+            STATE.assign_mode_active.store(false, Ordering::SeqCst);
+        } else {
+            // Should not happen - we are in assignment mode without item ID
+            eprintln!("Assignment mode active, but no item ID available");
+            STATE.assign_mode_active.store(false, Ordering::SeqCst);
+            
+            // Send error message with timestamp
+            let payload = serde_json::json!({
+                "status": "error",
+                "message": "Assignment mode active without item ID",
+                "timestamp": get_formatted_timestamp(),
+            });
+            if let Err(e) = frontend::send_event("assign-mode-error", &payload.to_string()) {
+                eprintln!("Error sending Assign-Mode-Error event: {}", e);
+            }
+        }
+        
+        return; // No further processing in assignment mode
+    }
+    
+    // Normal processing when not in assignment mode
+    let layer = active_layer();
+    let script_content = {
+        let items_guard = STATE.items.lock().unwrap();
+        let items = match &*items_guard {
+            Some(items) => items,
+            None => {
+                println!("No items available");
                 
                 // Send error message with timestamp
-                let timestamp = get_formatted_timestamp();
-                if let Err(e) = frontend::send_event("key-not-found", &format!("{{\"status\":\"error\",\"key\":\"{}\",\"message\":\"No item found with this key\",\"timestamp\":\"{}\"}}", key_name, timestamp)) {
-                    eprintln!("Error sending Key-Not-Found event: {}", e);
+                let payload = serde_json::json!({
+                    "status": "error",
+                    "message": "No items available",
+                    "timestamp": get_formatted_timestamp(),
+                });
+                if let Err(e) = frontend::send_event("items-error", &payload.to_string()) {
+                    eprintln!("Error sending Items-Error event: {}", e);
                 }
-                None
+                return;
+            }
+        };
+        
+        if let Some(item) = find_item_for_key(items, key_name, device_name, device_id, gesture, layer.as_deref()) {
+            println!("Found matching item: {} with key {}", item.display_text, item.assigned_key);
+            Some((item.id.clone(), item.display_text.clone(), item.content.clone(), item.layer_switch.clone()))
+        } else {
+            println!("No item found with key: {} ({})", key_name, gesture);
+            
+            // Send error message with timestamp
+            let payload = serde_json::json!({
+                "status": "error",
+                "key": key_name,
+                "gesture": gesture.to_string(),
+                "message": "No item found with this key",
+                "timestamp": get_formatted_timestamp(),
+            });
+            if let Err(e) = frontend::send_event("key-not-found", &payload.to_string()) {
+                eprintln!("Error sending Key-Not-Found event: {}", e);
+            }
+            None
+        }
+    };
+    
+    // Lua-Skript ausführen und Fehler an Frontend senden
+    if let Some((item_id, item_name, content, layer_switch)) = script_content {
+        // Remember the item for auto repeat and the key-up script on the keys that are still down.
+        // A chord is reported when its first key goes up, it is released with the last one.
+        // A sequence is released with its last key.
+        let still_held = {
+            let keys = trigger_keys(key_name);
+            let mut held_keys = STATE.held_keys.lock().unwrap();
+            let mut still_held = false;
+            for held in held_keys.iter_mut().filter(|held| held.device_id == device_id && keys.contains(&held.key.as_str())) {
+                held.item = Some((item_id.clone(), key_name.to_string(), gesture));
+                still_held = true;
             }
+            still_held
         };
         
-        // Lua-Skript ausführen und Fehler an Frontend senden
-        if let Some((item_id, item_name, content)) = script_content {
-            match LuaManager::new() {
-                Ok(lua_script) => {
-                    println!("Executing Lua script for item: {}", item_name);
-                    // Pass the item name to an extended run_script method
-                    match lua_script.run_script_with_name(&content, key_name) {
-                        Ok(_) => {
-                            println!("Lua script executed successfully");
-                            // Send success message with timestamp to frontend
-                            let timestamp = get_formatted_timestamp();
-                            if let Err(e) = frontend::send_event("lua-execution", &format!("{{\"status\":\"success\",\"itemId\":\"{}\",\"itemName\":\"{}\",\"timestamp\":\"{}\"}}", item_id, item_name, timestamp)) {
-                                eprintln!("Error sending success event: {}", e);
-                            }
-                        },
-                        Err(e) => {
-                            let error_msg = format!("Error executing Lua script: {}", e);
-                            eprintln!("{}", error_msg);
-                            
-                            // Send error message with timestamp to frontend
-                            let timestamp = get_formatted_timestamp();
-                            let error_payload = format!("{{\"status\":\"error\",\"itemId\":\"{}\",\"itemName\":\"{}\",\"error\":\"{}\",\"timestamp\":\"{}\"}}",
-                                                      item_id, 
-                                                      item_name, 
-                                                      e.to_string().replace("\"", "\\\"").replace("\n", "\\n"),
-                                                      timestamp);
-                            
-                            if let Err(send_err) = frontend::send_event("lua-error", &error_payload) {
-                                eprintln!("Error sending error event: {}", send_err);
-                            }
-                        }
+        match layer_switch {
+            Some(switch) => switch_layer(&item_id, &switch),
+            None => run_item_script(&item_id, &item_name, &content, key_name, device_name, delta, gesture),
+        }
+        
+        // A tap is only recognized after the release when the key also has a double tap item
+        if !still_held {
+            release_item(&item_id, key_name, device_name, delta, gesture);
+        }
+    }
+}
+
+// Keys that hold a trigger down: the keys of a chord, the last key of a sequence, otherwise the key itself
+fn trigger_keys(key_name: &str) -> Vec<&str> {
+    if let Some(keys) = macroeng::key_codes::sequence_keys(key_name) {
+        return keys.last().copied().into_iter().collect();
+    }
+    macroeng::key_codes::chord_keys(key_name).unwrap_or_else(|| vec![key_name])
+}
+
+// Active layer, None = base layer
+fn active_layer() -> Option<String> {
+    STATE.layers.lock().unwrap().active().map(str::to_string)
+}
+
+// Layer key of an item pressed
+fn switch_layer(item_id: &str, switch: &LayerSwitch) {
+    change_layers(|layers| match switch.mode {
+        LayerMode::Momentary => {
+            layers.held.retain(|(id, _)| id != item_id);
+            layers.held.push((item_id.to_string(), switch.layer.clone()));
+        },
+        LayerMode::Toggle => {
+            layers.toggled = if layers.toggled.as_deref() == Some(switch.layer.as_str()) {
+                None
+            } else {
+                Some(switch.layer.clone())
+            };
+        },
+    });
+}
+
+// Changes the layers and reports a new active layer to the frontend
+fn change_layers(change: impl FnOnce(&mut Layers)) {
+    let changed = {
+        let mut layers = STATE.layers.lock().unwrap();
+        let before = layers.active().map(str::to_string);
+        change(&mut layers);
+        let after = layers.active().map(str::to_string);
+        (before != after).then_some(after)
+    };
+    
+    if let Some(layer) = changed {
+        println!("Active layer: {}", layer.as_deref().unwrap_or("base"));
+        apply_layer_leds(layer.as_deref());
+        let payload = serde_json::json!({
+            "layer": layer,
+            "timestamp": get_formatted_timestamp(),
+        });
+        if let Err(e) = frontend::send_event("layer-changed", &payload.to_string()) {
+            eprintln!("Error sending Layer-Changed event: {}", e);
+        }
+    }
+}
+
+// Switches the layer LEDs of the blocked devices: on for the active layer, off for the others.
+// Layers may share an LED, it is on if any of them is active.
+fn apply_layer_leds(active: Option<&str>) {
+    let layer_leds = STATE.layer_leds.lock().unwrap().clone();
+    let keyb = KeyboardListener::Instance::new();
+    for (_, led) in &layer_leds {
+        let on = layer_leds.iter().any(|(layer, other)| other == led && active == Some(layer.as_str()));
+        keyb.set_led(*led, on, None);
+    }
+}
+
+// Counts the running macros, scripts of several keys can run at the same time.
+// The indicator LED stays on until the last one has ended.
+fn set_macro_running(running: bool) {
+    let mut running_macros = STATE.running_macros.lock().unwrap();
+    if running {
+        *running_macros += 1;
+    } else {
+        *running_macros = running_macros.saturating_sub(1);
+    }
+    
+    if let Some(led) = *STATE.macro_running_led.lock().unwrap() {
+        KeyboardListener::Instance::new().set_led(led, *running_macros > 0, None);
+    }
+}
+
+// Lua manager for an item script, sending to the output backend unless another sink is set
+fn new_lua_manager() -> Result<LuaManager, Box<dyn std::error::Error>> {
+    match STATE.script_output.lock().unwrap().clone() {
+        Some(output) => LuaManager::with_output(output),
+        None => LuaManager::new(),
+    }
+}
+
+// Runs a Lua script of an item and reports the result to the frontend
+fn run_item_script(item_id: &str, item_name: &str, content: &str, key_name: &str, device_name: &str, delta: i32, gesture: Gesture) {
+    match new_lua_manager() {
+        Ok(lua_script) => {
+            println!("Executing Lua script for item: {}", item_name);
+            if let Err(e) = lua_script.set_trigger(key_name, device_name, delta, gesture, active_layer().as_deref()) {
+                eprintln!("Error passing trigger to Lua: {}", e);
+            }
+            // Indicator LED while the macro runs
+            set_macro_running(true);
+            
+            // Pass the item name to an extended run_script method
+            let result = lua_script.run_script_with_name(content, key_name);
+            
+            set_macro_running(false);
+            
+            match result {
+                Ok(_) => {
+                    println!("Lua script executed successfully");
+                    // Send success message with timestamp to frontend
+                    let payload = serde_json::json!({
+                        "status": "success",
+                        "itemId": item_id,
+                        "itemName": item_name,
+                        "timestamp": get_formatted_timestamp(),
+                    });
+                    if let Err(e) = frontend::send_event("lua-execution", &payload.to_string()) {
+                        eprintln!("Error sending success event: {}", e);
                     }
                 },
                 Err(e) => {
-                    let error_msg = format!("Error creating Lua manager: {}", e);
+                    let error_msg = format!("Error executing Lua script: {}", e);
                     eprintln!("{}", error_msg);
                     
-                    // Send error message when creating the Lua manager with timestamp to frontend
-                    let timestamp = get_formatted_timestamp();
-                    let error_payload = format!("{{\"status\":\"error\",\"itemId\":\"{}\",\"itemName\":\"{}\",\"error\":\"Lua manager initialization failed: {}\",\"timestamp\":\"{}\"}}",
-                                              item_id, 
-                                              item_name, 
-                                              e.to_string().replace("\"", "\\\"").replace("\n", "\\n"),
-                                              timestamp);
+                    // Send error message with timestamp to frontend
+                    let error_payload = serde_json::json!({
+                        "status": "error",
+                        "itemId": item_id,
+                        "itemName": item_name,
+                        "error": e.to_string(),
+                        "timestamp": get_formatted_timestamp(),
+                    });
                     
-                    if let Err(send_err) = frontend::send_event("lua-error", &error_payload) {
-                        eprintln!("Error sending manager error event: {}", send_err);
+                    if let Err(send_err) = frontend::send_event("lua-error", &error_payload.to_string()) {
+                        eprintln!("Error sending error event: {}", send_err);
                     }
                 }
             }
+        },
+        Err(e) => {
+            let error_msg = format!("Error creating Lua manager: {}", e);
+            eprintln!("{}", error_msg);
+            
+            // Send error message when creating the Lua manager with timestamp to frontend
+            let error_payload = serde_json::json!({
+                "status": "error",
+                "itemId": item_id,
+                "itemName": item_name,
+                "error": format!("Lua manager initialization failed: {}", e),
+                "timestamp": get_formatted_timestamp(),
+            });
+            
+            if let Err(send_err) = frontend::send_event("lua-error", &error_payload.to_string()) {
+                eprintln!("Error sending manager error event: {}", send_err);
+            }
         }
     }
 }
 
 
-fn is_key_already_assigned(key: &str, exclude_item_id: &str) -> bool {
+// Find the item for a gesture of a key from a device on the active layer, falling back to the base layer.
+// Bindings for this exact device win over bindings for any device.
+fn find_item_for_key<'a>(items: &'a [Item], key_name: &str, device_name: &str, device_id: &str, gesture: Gesture, layer: Option<&str>) -> Option<&'a Item> {
+    layer.and_then(|layer| find_item_on_layer(items, key_name, device_name, device_id, gesture, Some(layer)))
+        .or_else(|| find_item_on_layer(items, key_name, device_name, device_id, gesture, None))
+}
+
+fn find_item_on_layer<'a>(items: &'a [Item], key_name: &str, device_name: &str, device_id: &str, gesture: Gesture, layer: Option<&str>) -> Option<&'a Item> {
+    let mut candidates = items.iter()
+        .filter(|item| item.assigned_key == key_name && item.assigned_gesture == gesture && item.layer.as_deref() == layer);
+    
+    candidates.clone()
+        .find(|item| item.has_device() && item.is_for_device(device_name, device_id))
+        .or_else(|| candidates.find(|item| !item.has_device()))
+}
+
+// Bindings are the same if they name the same device: by id if there is one, by name otherwise
+fn is_key_already_assigned(key: &str, device: Option<&str>, device_id: Option<&str>, gesture: Gesture, layer: Option<&str>, exclude_item_id: &str) -> bool {
     let items_guard = STATE.items.lock().unwrap();
+    let same_device = |item: &Item| match (item.assigned_device_id.as_deref(), device_id) {
+        (Some(saved), Some(id)) => KeyboardListener::Instance::new().is_bound_device(saved, id),
+        (saved, id) => saved.or(item.assigned_device.as_deref()) == id.or(device),
+    };
     
     match &*items_guard {
         Some(items) => items.iter()
-            .any(|item| item.assigned_key == key && item.id != exclude_item_id),
+            .any(|item| item.assigned_key == key && same_device(item)
+                && item.assigned_gesture == gesture && item.layer.as_deref() == layer && item.id != exclude_item_id),
         None => false,
     }
 }
 
-// Helper function to assign a key to an item
-fn assign_key_to_item(item_id: &str, key: String) -> Result<(), String> {
+// Helper function to assign a key (and the device and gesture it belongs to) to an item.
+// `device` is the device name, `device_id` its DeviceInfo::device_id (None = match the name only).
+fn assign_key_to_item(item_id: &str, key: String, device: Option<String>, device_id: Option<String>, gesture: Gesture) -> Result<(), String> {
     init_items();
     
-    // Check if the key is already assigned to another item
-    if is_key_already_assigned(&key, item_id) {
-        return Err(format!("Key '{}' is already assigned to another item", key));
+    // Check if the key is already assigned to another item on the layer of this item
+    let layer = get_item_by_id(item_id).and_then(|item| item.layer);
+    if is_key_already_assigned(&key, device.as_deref(), device_id.as_deref(), gesture, layer.as_deref(), item_id) {
+        return Err(match &device {
+            Some(device) => format!("Key '{}' ({}) on '{}' is already assigned to another item", key, gesture, device),
+            None => format!("Key '{}' ({}) is already assigned to another item", key, gesture),
+        });
     }
     
     match &mut *STATE.items.lock().unwrap() {
@@ -375,6 +974,9 @@ fn assign_key_to_item(item_id: &str, key: String) -> Result<(), String> {
             if let Some(item) = items.iter_mut().find(|item| item.id == item_id) {
                 // Update the assigned key
                 item.assigned_key = key;
+                item.assigned_device = device;
+                item.assigned_device_id = device_id;
+                item.assigned_gesture = gesture;
                 
                 // Save the changes immediately to the file
                 save_items_to_file(items).map_err(|e| format!("Error saving items: {}", e))?;
@@ -390,8 +992,9 @@ fn assign_key_to_item(item_id: &str, key: String) -> Result<(), String> {
 // Add new Tauri commands
 
 // Start assignment mode for a specific item
+// With `any_device` the key is bound regardless of which device it comes from
 #[tauri::command]
-fn start_assign_mode(id: String) -> Result<(), String> {
+fn start_assign_mode(id: String, any_device: Option<bool>) -> Result<(), String> {
     println!("Starte Key-Zuweisungsmodus für Item ID: {}", id);
     
     // Check if keyboard blocking is active
@@ -413,6 +1016,7 @@ fn start_assign_mode(id: String) -> Result<(), String> {
     }
     
     // Activate assignment mode
+    STATE.assign_any_device.store(any_device.unwrap_or(false), Ordering::SeqCst);
     *STATE.item_waiting_for_key.lock().unwrap() = Some(id);
     STATE.assign_mode_active.store(true, Ordering::SeqCst);
     
@@ -441,51 +1045,387 @@ fn get_assign_mode_status() -> (bool, Option<String>) {
     (is_active, item_id)
 }
 
-// Start blocking a device
-fn start_blocking_device(device: &KeyboardListener::DeviceInfo) -> Result<(), String> {
-    // Save device information
-    let device_name = device.device_name.clone();
-    *STATE.current_device.lock().unwrap() = Some(device_name.clone());
+// Change the device an item's key is bound to, None = any device.
+// The device is given by name or by its id (DeviceInfo::device_id).
+#[tauri::command]
+fn set_item_device(id: String, device: Option<String>) -> Result<(), String> {
+    init_items();
     
-    // Set flag for active blocking
-    STATE.blocking_active.store(true, Ordering::SeqCst);
+    let (key, gesture) = get_item_binding(&id)?;
+    let (device, device_id) = match device {
+        Some(device) => {
+            let (name, device_id) = resolve_blocked_device(&device);
+            (Some(name), device_id)
+        },
+        None => (None, None),
+    };
+    assign_key_to_item(&id, key, device, device_id, gesture)
+}
+
+// Name and id of a blocked device given by id or name. An id saved on another USB port resolves to the
+// device now connected (DeviceInfo::is_bound_to) and is stored with its current id.
+// A name shared by identical devices is ambiguous, it stays without id and matches all of them.
+fn resolve_blocked_device(device: &str) -> (String, Option<String>) {
+    let monitors = STATE.monitors.lock().unwrap();
+    let devices: Vec<&KeyboardListener::DeviceInfo> = monitors.iter().map(|monitor| monitor.device()).collect();
+    let connected: Vec<String> = devices.iter().map(|found| found.device_id()).collect();
+    
+    if let Some(found) = devices.iter().find(|found| found.is_bound_to(device, &connected)) {
+        return (found.device_name.clone(), Some(found.device_id()));
+    }
     
-    // Clone device for thread
-    let dev_clone = device.clone();
-    let thread_device_name = device_name.clone(); // Extra clone for thread
+    let named: Vec<&&KeyboardListener::DeviceInfo> = devices.iter().filter(|found| found.device_name == device).collect();
+    match named.as_slice() {
+        [found] => (device.to_string(), Some(found.device_id())),
+        _ => (device.to_string(), None),
+    }
+}
+
+// Device name and id the key of an item is bound to
+fn get_item_device_binding(id: &str) -> Result<(Option<String>, Option<String>), String> {
+    match &*STATE.items.lock().unwrap() {
+        Some(items) => items.iter()
+            .find(|item| item.id == id)
+            .map(|item| (item.assigned_device.clone(), item.assigned_device_id.clone()))
+            .ok_or_else(|| "Item with the specified ID not found".to_string()),
+        None => Err("No items available".to_string()),
+    }
+}
+
+// Key and gesture an item is bound to
+fn get_item_binding(id: &str) -> Result<(String, Gesture), String> {
+    match &*STATE.items.lock().unwrap() {
+        Some(items) => items.iter()
+            .find(|item| item.id == id)
+            .map(|item| (item.assigned_key.clone(), item.assigned_gesture))
+            .ok_or_else(|| "Item with the specified ID not found".to_string()),
+        None => Err("No items available".to_string()),
+    }
+}
+
+// Get the device the key of an item is bound to, None = any device
+#[tauri::command]
+fn get_item_device(id: String) -> Result<Option<String>, String> {
+    init_items();
     
-    // Create blocking thread
-    let handle = thread::spawn(move || {
-        println!("Starting blocking for device: {}", thread_device_name);
-        
-        // Check for immediate termination
-        if !STATE.blocking_active.load(Ordering::SeqCst) {
-            println!("Blocking cancelled before it started for device: {}", thread_device_name);
-            return;
+    match &*STATE.items.lock().unwrap() {
+        Some(items) => items.iter()
+            .find(|item| item.id == id)
+            .map(|item| item.assigned_device.clone())
+            .ok_or_else(|| "Item with the specified ID not found".to_string()),
+        None => Err("No items available".to_string()),
+    }
+}
+
+// Change the gesture (tap, doubletap, hold) that runs an item
+#[tauri::command]
+fn set_item_gesture(id: String, gesture: Gesture) -> Result<(), String> {
+    init_items();
+    
+    let (key, _) = get_item_binding(&id)?;
+    let (device, device_id) = get_item_device_binding(&id)?;
+    assign_key_to_item(&id, key, device, device_id, gesture)
+}
+
+// Get the gesture that runs an item
+#[tauri::command]
+fn get_item_gesture(id: String) -> Result<Gesture, String> {
+    init_items();
+    get_item_binding(&id).map(|(_, gesture)| gesture)
+}
+
+// Set the script that runs when the key of an item is released, None or empty to remove it
+#[tauri::command]
+fn set_item_key_up_script(id: String, content: Option<String>) -> Result<(), String> {
+    update_item(&id, |item| item.key_up_content = content.filter(|content| !content.trim().is_empty()))
+}
+
+#[tauri::command]
+fn get_item_key_up_script(id: String) -> Result<Option<String>, String> {
+    init_items();
+    get_item_by_id(&id)
+        .map(|item| item.key_up_content)
+        .ok_or_else(|| "Item with the specified ID not found".to_string())
+}
+
+// Run the script of an item again on every auto repeat while its key is held
+#[tauri::command]
+fn set_item_repeat(id: String, repeat: bool) -> Result<(), String> {
+    update_item(&id, |item| item.repeat = repeat)
+}
+
+#[tauri::command]
+fn get_item_repeat(id: String) -> Result<bool, String> {
+    init_items();
+    get_item_by_id(&id)
+        .map(|item| item.repeat)
+        .ok_or_else(|| "Item with the specified ID not found".to_string())
+}
+
+// Put the key binding of an item on a layer, None or empty = base layer
+#[tauri::command]
+fn set_item_layer(id: String, layer: Option<String>) -> Result<(), String> {
+    init_items();
+    
+    let layer = layer.filter(|layer| !layer.trim().is_empty());
+    let (key, gesture) = get_item_binding(&id)?;
+    let (device, device_id) = get_item_device_binding(&id)?;
+    if is_key_already_assigned(&key, device.as_deref(), device_id.as_deref(), gesture, layer.as_deref(), &id) {
+        return Err(format!("Key '{}' ({}) is already assigned to another item on layer '{}'", key, gesture, layer.as_deref().unwrap_or("base")));
+    }
+    update_item(&id, |item| item.layer = layer)
+}
+
+#[tauri::command]
+fn get_item_layer(id: String) -> Result<Option<String>, String> {
+    init_items();
+    get_item_by_id(&id)
+        .map(|item| item.layer)
+        .ok_or_else(|| "Item with the specified ID not found".to_string())
+}
+
+// Make the key of an item a momentary or toggle layer key, None = normal item that runs its script
+#[tauri::command]
+fn set_item_layer_switch(id: String, layer_switch: Option<LayerSwitch>) -> Result<(), String> {
+    if layer_switch.as_ref().is_some_and(|switch| switch.layer.trim().is_empty()) {
+        return Err("Layer name must not be empty".to_string());
+    }
+    update_item(&id, |item| item.layer_switch = layer_switch)
+}
+
+#[tauri::command]
+fn get_item_layer_switch(id: String) -> Result<Option<LayerSwitch>, String> {
+    init_items();
+    get_item_by_id(&id)
+        .map(|item| item.layer_switch)
+        .ok_or_else(|| "Item with the specified ID not found".to_string())
+}
+
+// Drop a partly typed sequence without running its keys
+#[tauri::command]
+fn cancel_sequence() {
+    Gestures::new().cancel_sequences();
+}
+
+// Layer the keys are currently resolved against, None = base layer
+#[tauri::command]
+fn get_active_layer() -> Option<String> {
+    active_layer()
+}
+
+// Change an item and save the items
+fn update_item(id: &str, update: impl FnOnce(&mut Item)) -> Result<(), String> {
+    init_items();
+    
+    match &mut *STATE.items.lock().unwrap() {
+        Some(items) => {
+            let item = items.iter_mut()
+                .find(|item| item.id == id)
+                .ok_or_else(|| "Item with the specified ID not found".to_string())?;
+            update(item);
+            save_items_to_file(items).map_err(|e| format!("Error saving items: {}", e))
+        },
+        None => Err("No items available".to_string()),
+    }
+}
+
+// Selective grab: keys without an item (and everything that is no key) keep working as normal input
+fn apply_selective_grab(enabled: bool) {
+    let keyb = KeyboardListener::Instance::new();
+    if enabled {
+        init_items();
+        keyb.set_trigger_filter(Some(Arc::new(|info: &KeyboardListener::KeyInfo| {
+            // Every key has to reach handle_key while a key is being assigned
+            if STATE.assign_mode_active.load(Ordering::SeqCst) {
+                return true;
+            }
+            match &*STATE.items.lock().unwrap() {
+                Some(items) => is_key_bound(items, &info.name, &info.device, &info.device_id)
+                    || is_chord_member(items, &info.name, &info.device, &info.device_id),
+                None => false,
+            }
+        })));
+    } else {
+        keyb.set_trigger_filter(None);
+    }
+}
+
+#[tauri::command]
+fn set_selective_grab(enabled: bool) -> Result<(), String> {
+    let mut settings = load_settings();
+    settings.selective_grab = enabled;
+    save_settings_to_file(&settings)?;
+    
+    apply_selective_grab(enabled);
+    println!("Selective grab {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+#[tauri::command]
+fn get_selective_grab() -> bool {
+    load_settings().selective_grab
+}
+
+// LED that is switched on while a macro runs, None to switch the indicator off
+#[tauri::command]
+fn set_macro_running_led(led: Option<String>) -> Result<(), String> {
+    let code = match &led {
+        Some(name) => Some(macroeng::key_codes::string_to_led(name).ok_or_else(|| format!("Unknown LED: {}", name))?),
+        None => None,
+    };
+    
+    let mut settings = load_settings();
+    settings.macro_running_led = led;
+    save_settings_to_file(&settings)?;
+    
+    *STATE.macro_running_led.lock().unwrap() = code;
+    Ok(())
+}
+
+// LED that is on while the layer is active, None to remove it
+#[tauri::command]
+fn set_layer_led(layer: String, led: Option<String>) -> Result<(), String> {
+    let code = match &led {
+        Some(name) => Some(macroeng::key_codes::string_to_led(name).ok_or_else(|| format!("Unknown LED: {}", name))?),
+        None => None,
+    };
+    
+    let mut settings = load_settings();
+    match led {
+        Some(name) => settings.layer_leds.insert(layer.clone(), name),
+        None => settings.layer_leds.remove(&layer),
+    };
+    save_settings_to_file(&settings)?;
+    
+    {
+        let mut layer_leds = STATE.layer_leds.lock().unwrap();
+        if let Some(index) = layer_leds.iter().position(|(other, _)| *other == layer) {
+            let (_, old_led) = layer_leds.remove(index);
+            // The LED is free again unless another layer uses it
+            if !layer_leds.iter().any(|(_, other)| *other == old_led) {
+                KeyboardListener::Instance::new().set_led(old_led, false, None);
+            }
         }
-        
-        let keyb = KeyboardListener::Instance::new();
-        if let Err(e) = keyb.block_input_device(&dev_clone) {
-            eprintln!("Error blocking device: {}", e);
+        if let Some(code) = code {
+            layer_leds.push((layer, code));
         }
-        
-        // Reached when blocking stops
-        println!("Blocking thread for {} has ended", thread_device_name);
-        STATE.blocking_active.store(false, Ordering::SeqCst);
-    });
+    }
+    apply_layer_leds(active_layer().as_deref());
+    Ok(())
+}
+
+// Hold and double tap thresholds in milliseconds
+#[tauri::command]
+fn set_gesture_timings(timings: GestureTimings) -> Result<(), String> {
+    if timings.hold_ms == 0 || timings.double_tap_ms == 0 || timings.sequence_ms == 0 {
+        return Err("Gesture timings must be greater than 0".to_string());
+    }
+    
+    let mut settings = load_settings();
+    settings.gesture_timings = timings;
+    save_settings_to_file(&settings)?;
+    
+    Gestures::new().set_timings(timings);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_gesture_timings() -> GestureTimings {
+    load_settings().gesture_timings
+}
+
+// How macros send keys: "auto" (XTest on X11, uinput otherwise), "xtest" or "uinput"
+#[tauri::command]
+fn set_output_backend(backend: OutputBackend) -> Result<(), String> {
+    // Fail now instead of in the next macro
+    backend.open().map_err(|e| format!("Output backend '{:?}' is not available: {}", backend, e))?;
     
-    // Save thread handle
-    *STATE.blocking_thread.lock().unwrap() = Some(handle);
+    let mut settings = load_settings();
+    settings.output_backend = backend;
+    save_settings_to_file(&settings)?;
     
-    println!("Successfully started blocking keyboard: {}", device_name);
+    OutputBackend::set_default(backend);
     Ok(())
 }
 
+#[tauri::command]
+fn get_output_backend() -> OutputBackend {
+    load_settings().output_backend
+}
+
+// Start blocking a device in its own thread, other blocked devices keep running
+fn start_blocking_device(device: &KeyboardListener::DeviceInfo) -> Result<(), String> {
+    let keyb = KeyboardListener::Instance::new();
+    if keyb.is_monitoring(&device.device_path) {
+        return Err(format!("Device {} is already blocked", device.device_name));
+    }
+    
+    println!("Starting blocking for device: {}", device.device_name);
+    let monitor = keyb.start_monitor(device)
+        .map_err(|e| format!("Error blocking device {}: {}", device.device_name, e))?;
+    
+    // Save monitor handle
+    STATE.monitors.lock().unwrap().push(monitor);
+    update_blocked_devices();
+    // Show the active layer on the new device too
+    apply_layer_leds(active_layer().as_deref());
+    
+    println!("Successfully started blocking keyboard: {}", device.device_name);
+    Ok(())
+}
+
+// Remove ended monitors (device unplugged) and update the list of blocked devices
+fn update_blocked_devices() {
+    let (names, ended) = {
+        let mut monitors = STATE.monitors.lock().unwrap();
+        let (running, ended): (Vec<_>, Vec<_>) = monitors.drain(..).partition(|monitor| monitor.is_running());
+        *monitors = running;
+        let names: Vec<String> = monitors.iter().map(|monitor| monitor.device().device_name.clone()).collect();
+        (names, ended)
+    };
+    
+    STATE.blocking_active.store(!names.is_empty(), Ordering::SeqCst);
+    *STATE.current_devices.lock().unwrap() = names;
+    
+    // Dropping a handle waits for its thread, so only after the lock is released
+    for monitor in ended {
+        println!("Blocking for {} has ended", monitor.device().device_name);
+    }
+}
+
+// Start blocking all given devices, returns the status text for the UI
+fn start_blocking_devices(devices: &[KeyboardListener::DeviceInfo]) -> Result<String, String> {
+    let mut blocked = Vec::new();
+    
+    for dev in devices {
+        match start_blocking_device(dev) {
+            Ok(_) => blocked.push(dev.device_name.clone()),
+            Err(e) => eprintln!("Error starting blocking for {}: {}", dev.device_name, e),
+        }
+    }
+    
+    if blocked.is_empty() {
+        return Err("No device could be blocked".to_string());
+    }
+    
+    Ok(format!("{} (Blocking active)", blocked.join(", ")))
+}
+
 // ====== Tauri Command Functions ======
+// Error for the device commands when the app runs without root
+fn check_device_access() -> Result<(), String> {
+    if STATE.device_access.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err("No access to the input devices, start MacroKeyB with root rights to detect and block devices".to_string())
+    }
+}
+
 // Load previously detected keyboard from config
 #[tauri::command]
 fn load_emited_keyboard() -> Result<String, String> {
     println!("Loading previously detected keyboard from JSON...");
+    check_device_access()?;
     
     // Check if detection is already in progress
     if STATE.detection_in_progress.load(Ordering::SeqCst) {
@@ -501,31 +1441,54 @@ fn load_emited_keyboard() -> Result<String, String> {
         }
     }
     
-    // Reset cancellation flag
-    STATE.cancel_detection.store(false, Ordering::SeqCst);
-    
     let keyb = KeyboardListener::Instance::new();
     let keyb_path = get_keyb_path();
     
+    let keyb_path_str = keyb_path.to_str().unwrap_or("/home/a7/keyb.json");
+    
     // Load devices from JSON
-    match keyb.load_devices_from_json(keyb_path.to_str().unwrap_or("/home/a7/keyb.json")) {
-        Ok(devices) => {
-            if devices.is_empty() {
+    match keyb.load_devices_from_json(keyb_path_str) {
+        Ok(saved_devices) => {
+            if saved_devices.is_empty() {
                 return Err("No saved devices found in config file".to_string());
             }
             
-            // Use first device
-            let dev = &devices[0];
-            println!("Successfully loaded keyboard: {}", dev.device_name);
-            
             // Add standard key listener
             add_standard_key_listener(&keyb);
             
-            // Start blocking
-            match start_blocking_device(dev) {
-                Ok(_) => Ok(format!("{} (Blocking active)", dev.device_name)),
-                Err(e) => Err(format!("Error starting blocking: {}", e)),
+            // Saved devices that are not connected yet are blocked when they get plugged in
+            STATE.auto_regrab.store(true, Ordering::SeqCst);
+            
+            // Find the saved devices by their properties, the event numbers may have changed
+            let resolved = keyb.resolve_saved_devices(&saved_devices);
+            let mut devices = Vec::new();
+            let mut updated_devices = Vec::new();
+            for (saved, found) in saved_devices.iter().zip(resolved) {
+                match found {
+                    Some(dev) => {
+                        println!("Successfully loaded keyboard: {} ({})", dev.device_name, dev.device_path);
+                        devices.push(dev.clone());
+                        updated_devices.push(dev);
+                    },
+                    None => {
+                        println!("Saved keyboard not connected: {}", saved.device_name);
+                        updated_devices.push(saved.clone());
+                    },
+                }
+            }
+            
+            if devices.is_empty() {
+                return Err("None of the saved devices is connected".to_string());
+            }
+            
+            // Store current paths and the IDs of entries saved by older versions
+            if let Err(e) = keyb.save_devices_to_json(&updated_devices, keyb_path_str) {
+                eprintln!("Error saving device config: {}", e);
             }
+            
+            // Start blocking every saved device
+            start_blocking_devices(&devices)
+                .map_err(|e| format!("Error starting blocking: {}", e))
         },
         Err(e) => Err(format!("Error loading device config: {}", e))
     }
@@ -536,6 +1499,9 @@ fn load_emited_keyboard() -> Result<String, String> {
 fn release_blocked_devices() -> Result<(), String> {
     println!("Releasing all blocked devices...");
     
+    // Don't block saved devices again when they get plugged in
+    STATE.auto_regrab.store(false, Ordering::SeqCst);
+    
     // Check if blocking is active
     if !STATE.blocking_active.load(Ordering::SeqCst) {
         println!("No active blocking to release");
@@ -545,38 +1511,35 @@ fn release_blocked_devices() -> Result<(), String> {
     // Set flag that blocking is no longer active (BEFORE stopping)
     STATE.blocking_active.store(false, Ordering::SeqCst);
     
-    // Stop monitor
-    let keyb = KeyboardListener::Instance::new();
-    keyb.stop_monitor_now();
-    
-    // Extract blocking thread handle
-    let thread_handle = {
-        let mut lock = STATE.blocking_thread.lock().unwrap();
-        lock.take()
-    };
+    // Take the monitor handles out of the state, stopping waits for the monitor threads
+    // and those lock STATE.monitors when they end
+    let monitors: Vec<_> = STATE.monitors.lock().unwrap().drain(..).collect();
     
-    // Wait for blocking thread to terminate
-    if let Some(handle) = thread_handle {
-        match handle.join() {
-            Ok(_) => println!("Blocking thread joined successfully"),
-            Err(_) => eprintln!("Error joining blocking thread"),
+    for monitor in monitors {
+        let device_name = monitor.device().device_name.clone();
+        match monitor.stop() {
+            Ok(_) => println!("Released device: {}", device_name),
+            Err(e) => eprintln!("Monitor of {} ended with an error: {}", device_name, e),
         }
     }
     
-    // Clear current device
-    *STATE.current_device.lock().unwrap() = None;
+    // Clear current devices
+    STATE.current_devices.lock().unwrap().clear();
     
     println!("All devices released successfully");
     Ok(())
 }
 
-// Wait for and detect keyboard input
+// Wait for key presses and return every device that produced input, the most active one first.
+// When more than one device fired the UI asks which one to use, see use_detected_device.
+// Empty if the detection timed out or was cancelled.
 #[tauri::command]
-async fn wait_for_keypress() -> Result<String, String> {
+async fn detect_input_devices() -> Result<Vec<KeyboardListener::DetectedDevice>, String> {
     println!("Starting keyboard detection...");
+    check_device_access()?;
     
     // Check if detection is already in progress
-    if STATE.detection_in_progress.load(Ordering::SeqCst) {
+    if STATE.detection_in_progress.swap(true, Ordering::SeqCst) {
         return Err("Detection already in progress".to_string());
     }
     
@@ -589,114 +1552,104 @@ async fn wait_for_keypress() -> Result<String, String> {
         }
     }
     
-    // Reset cancellation flag and set detection flag
-    STATE.cancel_detection.store(false, Ordering::SeqCst);
-    STATE.detection_in_progress.store(true, Ordering::SeqCst);
-    
-    // Get path to keyboard config file
-    let keyb_path = get_keyb_path();
-    let keyb_path_str = keyb_path.to_str().unwrap_or("/home/a7/keyb.json").to_string();
+    let cancel = match KeyboardListener::CancelToken::new() {
+        Ok(cancel) => cancel,
+        Err(e) => {
+            STATE.detection_in_progress.store(false, Ordering::SeqCst);
+            return Err(format!("Error starting detection: {}", e));
+        }
+    };
+    *STATE.cancel_detection.lock().unwrap() = Some(cancel.clone());
+    let cancelled = cancel.clone();
     
-    // Perform device detection in separate thread
-    let handle = task::spawn_blocking(move || {
+    // Perform device detection in separate thread, it ends after the timeout or when cancelled
+    let result = task::spawn_blocking(move || {
         let keyb = KeyboardListener::Instance::new();
         let device_list = keyb.get_device_list();
-        
-        // Check for cancellation
-        if STATE.cancel_detection.load(Ordering::SeqCst) {
-            return Err("Detection cancelled".to_string());
-        }
-        
-        // Start auto-detection
-        match keyb.auto_detect_input_device(device_list.as_slice()) {
-            Ok(opt) => {
-                // Check for cancellation again after detection
-                if STATE.cancel_detection.load(Ordering::SeqCst) {
-                    return Ok("Detection cancelled".to_string());
-                }
-                
-                if let Some(dev) = opt {
-                    // Detection successful
-                    println!("Successfully detected keyboard: {}", dev.device_name);
-                    
-                    // Add standard key listener
-                    add_standard_key_listener(&keyb);
-                    
-                    // Save device information to file
-                    if let Err(e) = keyb.save_devices_to_json(&[dev.clone()], &keyb_path_str) {
-                        eprintln!("Error saving device config: {}", e);
-                    }
-                    
-                    // Start blocking
-                    match start_blocking_device(&dev) {
-                        Ok(_) => Ok(format!("{} (Blocking active)", dev.device_name)),
-                        Err(e) => Ok(format!("Error starting blocking: {}", e)),
-                    }
-                } else {
-                    Ok("No input detected".into())
-                }
-            },
-            Err(e) => Err(e.to_string()),
-        }
-    });
-
-    // Add timeout
-    let result = match time::timeout(time::Duration::from_secs(180), handle).await {
-        Ok(result) => {
-            match result {
-                Ok(device_result) => device_result,
-                Err(e) => Err(format!("Error in detection task: {}", e)),
-            }
-        },
-        Err(_) => {
-            // Handle timeout
-            STATE.cancel_detection.store(true, Ordering::SeqCst);
-            Err("Timeout during detection".to_string())
-        }
-    };
+        keyb.detect_input_devices(&device_list, &cancel, KeyboardListener::DETECTION_TIMEOUT)
+            .map_err(|e| e.to_string())
+    }).await;
     
     // Detection is complete
+    *STATE.cancel_detection.lock().unwrap() = None;
     STATE.detection_in_progress.store(false, Ordering::SeqCst);
     
-    result
+    // Cancelled: block the saved devices again, only possible now that the detection flag is cleared
+    if cancelled.is_cancelled() {
+        let status = load_emited_keyboard()
+            .map_err(|e| format!("Detection cancelled, saved devices not blocked again: {}", e))?;
+        println!("Detection cancelled, {}", status);
+        return Ok(Vec::new());
+    }
+    
+    let candidates = result.map_err(|e| format!("Error in detection task: {}", e))??;
+    for candidate in &candidates {
+        println!("Detected device: {} ({} presses)", candidate.device.device_name, candidate.presses);
+    }
+    Ok(candidates)
 }
 
-// Cancel keyboard detection process
+// Save a detected device and start blocking it
+// With `append` the device is added to the saved devices instead of replacing them
 #[tauri::command]
-async fn cancel_keypress_detection() -> Result<(), String> {
-    println!("Cancelling keyboard detection and blocking");
+fn use_detected_device(device_path: String, append: Option<bool>) -> Result<String, String> {
+    check_device_access()?;
+    let append = append.unwrap_or(false);
     
-    // Set cancellation flag
-    STATE.cancel_detection.store(true, Ordering::SeqCst);
+    let keyb = KeyboardListener::Instance::new();
+    let dev = keyb.get_device_list().into_iter()
+        .find(|d| d.device_path == device_path)
+        .ok_or_else(|| format!("Device {} is no longer connected", device_path))?;
+    println!("Successfully detected keyboard: {}", dev.device_name);
     
-    // Release blocking if active
-    if STATE.blocking_active.load(Ordering::SeqCst) {
-        println!("blocking was active");
-        STATE.blocking_active.store(false, Ordering::SeqCst);
-        
-        // Stop monitor
-        let keyb = KeyboardListener::Instance::new();
-        keyb.stop_monitor_now();
-    }
+    // Get path to keyboard config file
+    let keyb_path = get_keyb_path();
+    let keyb_path_str = keyb_path.to_str().unwrap_or("/home/a7/keyb.json").to_string();
     
-    // Wait briefly for threads to respond to cancellation flag
-    time::sleep(time::Duration::from_millis(200)).await;
+    // Add standard key listener
+    add_standard_key_listener(&keyb);
     
-    // Clear detection flag
-    STATE.detection_in_progress.store(false, Ordering::SeqCst);
+    // Keep the previously saved devices when appending
+    let mut devices = if append {
+        keyb.load_devices_from_json(&keyb_path_str).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    if !devices.iter().any(|d| d.device_path == dev.device_path) {
+        devices.push(dev.clone());
+    }
     
-    // Clear current device
-    *STATE.current_device.lock().unwrap() = None;
+    // Save device information to file
+    if let Err(e) = keyb.save_devices_to_json(&devices, &keyb_path_str) {
+        eprintln!("Error saving device config: {}", e);
+    }
     
-    // Clean up thread handle without waiting
-    let _ = STATE.blocking_thread.lock().unwrap().take();
+    // Start blocking
+    STATE.auto_regrab.store(true, Ordering::SeqCst);
+    match start_blocking_devices(&devices) {
+        Ok(status) => Ok(status),
+        Err(e) => Ok(format!("Error starting blocking: {}", e)),
+    }
+}
 
-    println!("Keyboard detection and blocking cancelled successfully");
+// Cancel keyboard detection process
+#[tauri::command]
+async fn cancel_keypress_detection() -> Result<(), String> {
+    println!("Cancelling keyboard detection and blocking");
     
-    // Release devices and load new keyboard status (ignore errors)
-    let _ = release_blocked_devices();
-    let _ = load_emited_keyboard();
+    // The detection returns right away, detect_input_devices clears the detection flag
+    // and blocks the saved devices again
+    let cancel = STATE.cancel_detection.lock().unwrap().clone();
+    if let Some(cancel) = cancel {
+        cancel.cancel();
+        println!("Keyboard detection cancelled successfully");
+        return Ok(());
+    }
     
+    // No detection running (e.g. while choosing between detected devices): block the saved devices again
+    release_blocked_devices()?;
+    let status = load_emited_keyboard()?;
+    println!("Keyboard detection cancelled, {}", status);
     Ok(())
 }
 
@@ -752,6 +1705,7 @@ fn add_item() -> (String, String, String, String, bool) {
         id: unique_id.clone(),
         content: "".to_string(),
         is_selected: false,
+        ..Default::default()
     };
     
     let result = (
@@ -818,15 +1772,25 @@ fn delete_item(id: String) -> Result<(), String> {
 // Save all items (from UI)
 #[tauri::command]
 fn save_items(items: Vec<(String, String, String, String, bool)>) -> Result<(), String> {
+    // Fields the UI doesn't send (e.g. the bound device) are taken from the current items
+    let current_items = STATE.items.lock().unwrap().clone().unwrap_or_default();
+    
     // Convert back to Item structures
     let items: Vec<Item> = items
         .into_iter()
-        .map(|(display_text, assigned_key, id, content, is_selected)| Item {
-            display_text,
-            assigned_key,
-            id,
-            content,
-            is_selected,
+        .map(|(display_text, assigned_key, id, content, is_selected)| {
+            let current = current_items.iter()
+                .find(|item| item.id == id)
+                .cloned()
+                .unwrap_or_default();
+            Item {
+                display_text,
+                assigned_key,
+                id,
+                content,
+                is_selected,
+                ..current
+            }
         })
         .collect();
     
@@ -857,12 +1821,85 @@ fn update_item_content(id: String, content: String) -> Result<(), String> {
 
 // Get current status of keyboard locks
 #[tauri::command]
-fn get_keyboard_status() -> (bool, bool, Option<String>) {
+fn get_keyboard_status() -> (bool, bool, Vec<String>) {
+    update_blocked_devices();
     let is_blocking = STATE.blocking_active.load(Ordering::SeqCst);
     let is_detecting = STATE.detection_in_progress.load(Ordering::SeqCst);
-    let device = STATE.current_device.lock().unwrap().clone();
+    let devices = STATE.current_devices.lock().unwrap().clone();
+    
+    (is_blocking, is_detecting, devices)
+}
+
+// Start recording the raw events of every blocked device, one file per device in <config>/recordings.
+// Returns the paths of the recordings.
+#[tauri::command]
+fn start_recording() -> Result<Vec<String>, String> {
+    let keyb = KeyboardListener::Instance::new();
+    let devices = keyb.monitored_devices();
+    if devices.is_empty() {
+        return Err("No device is blocked".to_string());
+    }
+    
+    let dir = get_settings_path().with_file_name("recordings");
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating recordings directory: {}", e))?;
+    
+    let timestamp = Utc::now().format("%Y%m%d-%H%M%S");
+    let mut paths = Vec::new();
+    for (device_path, device_name) in devices {
+        let file_name = device_path.rsplit('/').next().unwrap_or("device");
+        let path = dir.join(format!("{}-{}.events", timestamp, file_name));
+        match keyb.start_recording(&device_path, &path) {
+            Ok(_) => paths.push(path.to_string_lossy().into_owned()),
+            Err(e) => eprintln!("Error starting recording for {}: {}", device_name, e),
+        }
+    }
+    
+    Ok(paths)
+}
+
+// Stop all recordings, returns the paths of the finished files
+#[tauri::command]
+fn stop_recording() -> Result<Vec<String>, String> {
+    let keyb = KeyboardListener::Instance::new();
+    let paths = keyb.monitored_devices()
+        .into_iter()
+        .filter_map(|(device_path, _)| keyb.stop_recording(&device_path))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    
+    Ok(paths)
+}
+
+// Feed a recording into the key handling as if the recorded device sent it.
+// speed: 1.0 (default) = real time, 2.0 = twice as fast, 0 = no delays
+#[tauri::command]
+fn replay_recording(path: String, speed: Option<f64>) -> Result<(), String> {
+    let source = macroeng::ReplaySource::open(std::path::Path::new(&path))
+        .map_err(|e| format!("Error reading recording {}: {}", path, e))?
+        .with_speed(speed.unwrap_or(1.0));
+    
+    // Bindings to the recorded device match as long as the recording names it
+    let device = KeyboardListener::DeviceInfo {
+        device_name: source.device_name().unwrap_or("Replay").to_string(),
+        device_path: format!("replay:{}", path),
+        ..Default::default()
+    };
+    
+    let keyb = KeyboardListener::Instance::new();
+    if keyb.is_monitoring(&device.device_path) {
+        return Err(format!("Recording {} is already being replayed", path));
+    }
+    add_standard_key_listener(&keyb);
+    
+    thread::spawn(move || {
+        println!("Replaying {} as {}", device.device_path, device.device_name);
+        if let Err(e) = keyb.monitor_source(&device, Box::new(source)) {
+            eprintln!("Error replaying {}: {}", device.device_path, e);
+        }
+        println!("Replay of {} finished", device.device_path);
+    });
     
-    (is_blocking, is_detecting, device)
+    Ok(())
 }
 
 
@@ -873,7 +1910,6 @@ pub fn run() {
     // Ensure no blocking or detection is active at startup
     STATE.blocking_active.store(false, Ordering::SeqCst);
     STATE.detection_in_progress.store(false, Ordering::SeqCst);
-    STATE.cancel_detection.store(false, Ordering::SeqCst);
     
     // Set application data directory
     match init_config_dir() {
@@ -893,6 +1929,30 @@ pub fn run() {
      .setup(|app| {
             // Event-Manager initialisieren
             frontend::init(app.handle().clone());
+            
+            // Reading and blocking real devices needs root. Without it the app still runs,
+            // XTest output and replaying recordings don't need it.
+            match KeyboardListener::Instance::admit_sudo() {
+                Ok(()) => STATE.device_access.store(true, Ordering::SeqCst),
+                Err(e) => eprintln!("{}, device detection and blocking are disabled", e),
+            }
+            let settings = load_settings();
+            apply_selective_grab(settings.selective_grab);
+            apply_gesture_filter();
+            Gestures::new().set_timings(settings.gesture_timings);
+            OutputBackend::set_default(settings.output_backend);
+            // Key names of scripts follow the X keyboard mapping, read it now and watch for changes
+            macroeng::keymap::key_table();
+            *STATE.macro_running_led.lock().unwrap() = settings.macro_running_led.as_deref()
+                .and_then(macroeng::key_codes::string_to_led);
+            *STATE.layer_leds.lock().unwrap() = settings.layer_leds.iter()
+                .filter_map(|(layer, led)| Some((layer.clone(), macroeng::key_codes::string_to_led(led)?)))
+                .collect();
+            
+            // Watch for plugged in / removed input devices
+            if STATE.device_access.load(Ordering::SeqCst) {
+                add_hotplug_listener(KeyboardListener::Instance::new());
+            }
 
 
             Ok(())
@@ -906,7 +1966,8 @@ pub fn run() {
             save_items,
             update_item_content,
             select_item,
-            wait_for_keypress,
+            detect_input_devices,
+            use_detected_device,
             cancel_keypress_detection,
             load_emited_keyboard,
             release_blocked_devices,
@@ -914,7 +1975,169 @@ pub fn run() {
             start_assign_mode,
             cancel_assign_mode,
             get_assign_mode_status,
+            set_item_device,
+            get_item_device,
+            start_recording,
+            stop_recording,
+            replay_recording,
+            set_selective_grab,
+            get_selective_grab,
+            set_macro_running_led,
+            set_layer_led,
+            set_item_gesture,
+            get_item_gesture,
+            set_item_key_up_script,
+            get_item_key_up_script,
+            set_item_repeat,
+            get_item_repeat,
+            set_item_layer,
+            get_item_layer,
+            set_item_layer_switch,
+            get_item_layer_switch,
+            get_active_layer,
+            cancel_sequence,
+            set_gesture_timings,
+            get_gesture_timings,
+            set_output_backend,
+            get_output_backend,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroeng::{OutputAction, RecordingSink};
+
+    // STATE and the listener are global, the tests take turns
+    static DISPATCH: Mutex<()> = Mutex::new(());
+
+    fn item(id: &str, key: &str, content: &str) -> Item {
+        Item {
+            id: id.to_string(),
+            display_text: id.to_string(),
+            assigned_key: key.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Replays the recorded events through the listener, gesture recognizer and dispatch,
+    // returns the keys the scripts of the items tapped
+    fn dispatch(items: Vec<Item>, recording: &str) -> Vec<String> {
+        let _turn = DISPATCH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let sink = Arc::new(RecordingSink::new());
+        *STATE.script_output.lock().unwrap() = Some(sink.clone());
+        *STATE.items.lock().unwrap() = Some(items);
+        *STATE.layers.lock().unwrap() = Layers { toggled: None, held: Vec::new() };
+        STATE.held_keys.lock().unwrap().clear();
+        apply_gesture_filter();
+
+        let path = std::env::temp_dir().join(format!("macrokeyb-dispatch-{}.txt", std::process::id()));
+        fs::write(&path, recording).unwrap();
+        let source = macroeng::ReplaySource::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        let device = KeyboardListener::DeviceInfo {
+            device_name: "Dispatch Test Pad".to_string(),
+            device_path: "replay:dispatch_test".to_string(),
+            ..Default::default()
+        };
+        let keyb = KeyboardListener::Instance::new();
+        add_standard_key_listener(keyb);
+        keyb.monitor_source(&device, Box::new(source.unwrap())).unwrap();
+
+        sink.events().iter()
+            .filter_map(|event| match event.action {
+                OutputAction::Key { keycode, pressed: true } => Some(macroeng::key_codes::key_to_string(keycode as u16 - 8)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn key_up_script_runs_on_release() {
+        let mut pressed = item("pressed", "A", "tap('x', 0)");
+        pressed.key_up_content = Some("tap('y', 0)".to_string());
+
+        let typed = dispatch(vec![pressed, item("other", "B", "tap('z', 0)")], "\
+1718000000.000000 1 30 1
+1718000000.050000 1 30 0
+1718000000.060000 1 48 1
+1718000000.070000 1 48 0
+");
+        assert_eq!(typed, ["X", "Y", "Z"]);
+    }
+
+    #[test]
+    fn repeating_item_runs_on_every_auto_repeat() {
+        let mut repeating = item("repeating", "A", "tap('x', 0)");
+        repeating.repeat = true;
+
+        let typed = dispatch(vec![repeating, item("once", "B", "tap('z', 0)")], "\
+1718000000.000000 1 30 1
+1718000000.020000 1 30 2
+1718000000.040000 1 30 2
+1718000000.050000 1 30 0
+1718000000.060000 1 48 1
+1718000000.070000 1 48 2
+1718000000.080000 1 48 0
+");
+        assert_eq!(typed, ["X", "X", "X", "Z"]);
+    }
+
+    #[test]
+    fn momentary_layer_is_active_while_its_key_is_held() {
+        let mut layer_key = item("layer", "L", "");
+        layer_key.layer_switch = Some(LayerSwitch { layer: "fn".to_string(), mode: LayerMode::Momentary });
+        let mut on_layer = item("on-layer", "B", "tap('y', 0)");
+        on_layer.layer = Some("fn".to_string());
+
+        let typed = dispatch(vec![layer_key, item("base", "B", "tap('x', 0)"), on_layer], "\
+1718000000.000000 1 38 1
+1718000000.010000 1 48 1
+1718000000.020000 1 48 0
+1718000000.030000 1 38 0
+1718000000.040000 1 48 1
+1718000000.050000 1 48 0
+");
+        assert_eq!(typed, ["Y", "X"]);
+    }
+
+    #[test]
+    fn toggle_layer_stays_until_toggled_off() {
+        let mut layer_key = item("layer", "L", "");
+        layer_key.layer_switch = Some(LayerSwitch { layer: "fn".to_string(), mode: LayerMode::Toggle });
+        let mut on_layer = item("on-layer", "B", "tap('y', 0)");
+        on_layer.layer = Some("fn".to_string());
+
+        let typed = dispatch(vec![layer_key, item("base", "B", "tap('x', 0)"), on_layer], "\
+1718000000.000000 1 38 1
+1718000000.010000 1 38 0
+1718000000.020000 1 48 1
+1718000000.030000 1 48 0
+1718000000.040000 1 38 1
+1718000000.050000 1 38 0
+1718000000.060000 1 48 1
+1718000000.070000 1 48 0
+");
+        assert_eq!(typed, ["Y", "X"]);
+    }
+
+    #[test]
+    fn device_binding_wins_over_any_device() {
+        let mut bound = item("bound", "A", "tap('y', 0)");
+        bound.assigned_device = Some("Dispatch Test Pad".to_string());
+        let mut other_device = item("other-device", "B", "tap('z', 0)");
+        other_device.assigned_device = Some("Other Pad".to_string());
+
+        let typed = dispatch(vec![item("any", "A", "tap('x', 0)"), bound, other_device], "\
+1718000000.000000 1 30 1
+1718000000.010000 1 30 0
+1718000000.020000 1 48 1
+1718000000.030000 1 48 0
+");
+        assert_eq!(typed, ["Y"]);
+    }
+}
@@ -16,3 +16,38 @@ listen('lua-error', (event) => {
   eventLuaError.set((event.payload as { message: string }).message);
 });
 
+
+// Macro device plugged in / removed
+export const eventDeviceStatus = writable<string>('');
+
+listen('device-connected', (event) => {
+  const device = JSON.parse((event.payload as { message: string }).message);
+  eventDeviceStatus.set(`Device connected: ${device.name} (${device.kind})`);
+});
+
+listen('device-disconnected', (event) => {
+  const device = JSON.parse((event.payload as { message: string }).message);
+  eventDeviceStatus.set(`Device disconnected: ${device.name}`);
+});
+
+
+// Active layer changed by a layer key (null = base layer)
+export const eventActiveLayer = writable<string>('');
+
+listen('layer-changed', (event) => {
+  const change = JSON.parse((event.payload as { message: string }).message);
+  eventActiveLayer.set(`Layer: ${change.layer ?? 'base'}`);
+});
+
+
+// Progress of a key sequence like "G>C"
+export const eventSequenceStep = writable<string>('');
+
+listen('sequence-step', (event) => {
+  const step = JSON.parse((event.payload as { message: string }).message);
+  if (step.status === 'waiting') {
+    eventSequenceStep.set(`${step.keys} … waiting for next key`);
+  } else {
+    eventSequenceStep.set('');
+  }
+});